@@ -0,0 +1,186 @@
+//! The disk from day 14: 128 rows of 128 squares, each row the knot hash of
+//! the key and the row number, one bit per square. Any number of rows works
+//! the same way, which makes for a handy thing to time.
+
+pub mod regions;
+
+use std::{
+    fmt,
+    io::{self, Write},
+    thread,
+};
+
+use crate::knot::KnotHash;
+
+pub use regions::{Bounds, Region, Regions};
+
+pub const WIDTH: usize = 128;
+
+/// One row of the disk, the leftmost square in the highest bit
+pub fn hash_row(key: &str, row: usize) -> u128 {
+    let hash: KnotHash = format!("{}-{}", key, row).parse().unwrap();
+    hash.dense()
+}
+
+/// Rows `0..rows`, split into even runs over `threads` threads. Each run
+/// goes straight into its own part of the result, so it comes out the same
+/// however many threads there are.
+pub fn hash_rows(key: &str, rows: usize, threads: usize) -> Vec<u128> {
+    let mut out = vec![0; rows];
+    let run = rows.div_ceil(threads.max(1)).max(1);
+    thread::scope(|s| {
+        for (n, chunk) in out.chunks_mut(run).enumerate() {
+            s.spawn(move || {
+                for (i, row) in chunk.iter_mut().enumerate() {
+                    *row = hash_row(key, n * run + i);
+                }
+            });
+        }
+    });
+    out
+}
+
+/// As many threads as the machine will run at once
+pub fn threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskGrid {
+    /// one per row, the leftmost square in the highest bit
+    pub rows: Vec<u128>,
+    labels: Regions,
+}
+
+impl DiskGrid {
+    pub fn new(key: &str) -> DiskGrid {
+        DiskGrid::with_rows(key, WIDTH)
+    }
+
+    /// A taller (or shorter) disk, its rows hashed on every thread going
+    pub fn with_rows(key: &str, rows: usize) -> DiskGrid {
+        DiskGrid::from_rows(hash_rows(key, rows, threads()))
+    }
+
+    pub fn from_rows(rows: Vec<u128>) -> DiskGrid {
+        let used: Vec<bool> = rows
+            .iter()
+            .flat_map(|row| (0..WIDTH).map(move |j| row & (1 << (WIDTH - 1 - j)) != 0))
+            .collect();
+        let labels = regions::flood_fill(&used, WIDTH);
+        DiskGrid { rows, labels }
+    }
+
+    pub fn height(&self) -> usize {
+        self.rows.len()
+    }
+
+    /// The answer to part 1
+    pub fn used(&self) -> u32 {
+        self.rows.iter().map(|row| row.count_ones()).sum()
+    }
+
+    /// Off the edge counts as free
+    pub fn is_used(&self, row: usize, col: usize) -> bool {
+        self.region(row, col).is_some()
+    }
+
+    /// `None` for free squares
+    pub fn region(&self, row: usize, col: usize) -> Option<usize> {
+        self.labels.label(row, col)
+    }
+
+    /// The answer to part 2
+    pub fn regions(&self) -> usize {
+        self.labels.count()
+    }
+
+    /// Every region's size and where it is
+    pub fn labels(&self) -> &Regions {
+        &self.labels
+    }
+
+    /// The top left corner as `#` for used and `.` for free, like the
+    /// puzzle's example
+    pub fn render(&self, rows: usize, cols: usize) -> String {
+        let lines: Vec<String> = (0..rows.min(self.height()))
+            .map(|i| {
+                (0..cols.min(WIDTH))
+                    .map(|j| if self.is_used(i, j) { '#' } else { '.' })
+                    .collect()
+            })
+            .collect();
+        lines.join("\n")
+    }
+
+    /// Black on white, `scale` pixels to a square
+    pub fn write_pbm<W: Write>(&self, mut out: W, scale: usize) -> io::Result<()> {
+        writeln!(out, "P1\n{} {}", WIDTH * scale, self.height() * scale)?;
+        for i in 0..self.height() * scale {
+            let values = (0..WIDTH * scale)
+                .map(|j| u8::from(self.is_used(i / scale, j / scale)).to_string());
+            write_wrapped(&mut out, values)?;
+        }
+        Ok(())
+    }
+
+    /// Each region in its own colour on black, `scale` pixels to a square
+    pub fn write_ppm<W: Write>(&self, mut out: W, scale: usize) -> io::Result<()> {
+        writeln!(out, "P3\n{} {}\n255", WIDTH * scale, self.height() * scale)?;
+        for i in 0..self.height() * scale {
+            let values = (0..WIDTH * scale).flat_map(|j| {
+                let (r, g, b) = match self.region(i / scale, j / scale) {
+                    Some(region) => colour(region),
+                    None => (0, 0, 0),
+                };
+                IntoIterator::into_iter([r, g, b]).map(|v| v.to_string())
+            });
+            write_wrapped(&mut out, values)?;
+        }
+        Ok(())
+    }
+}
+
+/// The plain netpbm formats don't allow lines over 70 characters
+const LINE: usize = 70;
+
+/// `values` separated by spaces, over as many lines as it takes
+fn write_wrapped<W: Write>(out: &mut W, values: impl Iterator<Item = String>) -> io::Result<()> {
+    let mut line = String::new();
+    for value in values {
+        if !line.is_empty() && line.len() + 1 + value.len() > LINE {
+            writeln!(out, "{}", line)?;
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line += &value;
+    }
+    writeln!(out, "{}", line)
+}
+
+/// Bright colours that step round the hue circle by the golden angle, so
+/// regions numbered close together (which tend to be close on the disk)
+/// look nothing alike
+fn colour(region: usize) -> (u8, u8, u8) {
+    let hue = (region as f64 * 137.507_764) % 360.0;
+    let x = 1.0 - ((hue / 60.0) % 2.0 - 1.0).abs();
+    let (r, g, b) = match (hue / 60.0) as u32 {
+        0 => (1.0, x, 0.0),
+        1 => (x, 1.0, 0.0),
+        2 => (0.0, 1.0, x),
+        3 => (0.0, x, 1.0),
+        4 => (x, 0.0, 1.0),
+        _ => (1.0, 0.0, x),
+    };
+    let byte = |v: f64| (55.0 + v * 200.0) as u8;
+    (byte(r), byte(g), byte(b))
+}
+
+/// The whole disk, one line per row
+impl fmt::Display for DiskGrid {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.render(self.height(), WIDTH))
+    }
+}
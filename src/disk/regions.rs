@@ -0,0 +1,167 @@
+//! Finding the regions of a grid of any size: groups of used squares joined
+//! up, down, left or right. There are two ways to do it, which give exactly
+//! the same answer, regions numbered from 0 in the order their first square
+//! turns up reading left to right, top to bottom.
+
+/// Inclusive at both ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bounds {
+    pub top: usize,
+    pub left: usize,
+    pub bottom: usize,
+    pub right: usize,
+}
+
+impl Bounds {
+    fn new(row: usize, col: usize) -> Bounds {
+        Bounds {
+            top: row,
+            left: col,
+            bottom: row,
+            right: col,
+        }
+    }
+
+    fn add(&mut self, row: usize, col: usize) {
+        self.top = self.top.min(row);
+        self.left = self.left.min(col);
+        self.bottom = self.bottom.max(row);
+        self.right = self.right.max(col);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    /// in squares
+    pub size: usize,
+    pub bounds: Bounds,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Regions {
+    pub width: usize,
+    /// which region each square is in, a row at a time, `None` if it's free
+    pub labels: Vec<Option<usize>>,
+    pub regions: Vec<Region>,
+}
+
+impl Regions {
+    /// Everything starts out free
+    fn new(width: usize, len: usize) -> Regions {
+        Regions {
+            width,
+            labels: vec![None; len],
+            regions: Vec::new(),
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.regions.len()
+    }
+
+    /// `None` for free squares, and off the edge
+    pub fn label(&self, row: usize, col: usize) -> Option<usize> {
+        if col >= self.width {
+            return None;
+        }
+        *self.labels.get(row * self.width + col)?
+    }
+
+    fn mark(&mut self, i: usize, region: usize) {
+        let (row, col) = (i / self.width, i % self.width);
+        self.labels[i] = Some(region);
+        match self.regions.get_mut(region) {
+            Some(r) => {
+                r.size += 1;
+                r.bounds.add(row, col);
+            }
+            None => self.regions.push(Region {
+                size: 1,
+                bounds: Bounds::new(row, col),
+            }),
+        }
+    }
+}
+
+/// Neighbours of square `i` that are on the grid
+fn neighbours(i: usize, width: usize, len: usize) -> impl Iterator<Item = usize> {
+    let up = i.checked_sub(width);
+    let left = Some(i).filter(|i| i % width > 0).map(|i| i - 1);
+    let right = Some(i + 1).filter(|i| i % width > 0);
+    let down = Some(i + width).filter(|&i| i < len);
+    IntoIterator::into_iter([up, left, right, down]).flatten()
+}
+
+/// Both ways of finding regions need whole rows
+fn check_shape(used: &[bool], width: usize) {
+    assert!(
+        width > 0 && used.len().is_multiple_of(width),
+        "{} squares don't make rows of {}",
+        used.len(),
+        width
+    );
+}
+
+/// `used` is the grid a row at a time, `width` squares to a row. Each region
+/// is filled from its first square, keeping the squares still to visit on a
+/// stack rather than recursing. Panics unless `width` divides the grid into
+/// whole rows.
+pub fn flood_fill(used: &[bool], width: usize) -> Regions {
+    check_shape(used, width);
+    let mut regions = Regions::new(width, used.len());
+    let mut stack = Vec::new();
+    for start in 0..used.len() {
+        if !used[start] || regions.labels[start].is_some() {
+            continue;
+        }
+        let region = regions.count();
+        regions.mark(start, region);
+        stack.push(start);
+        while let Some(i) = stack.pop() {
+            for n in neighbours(i, width, used.len()) {
+                if used[n] && regions.labels[n].is_none() {
+                    regions.mark(n, region);
+                    stack.push(n);
+                }
+            }
+        }
+    }
+    regions
+}
+
+/// Finds the set each square is in, halving paths as it goes
+fn find(parent: &mut [usize], mut i: usize) -> usize {
+    while parent[i] != i {
+        parent[i] = parent[parent[i]];
+        i = parent[i];
+    }
+    i
+}
+
+/// The same as `flood_fill`, but in two passes over the grid: the first joins
+/// each used square to the ones above and to the left of it, and the second
+/// numbers the sets.
+pub fn union_find(used: &[bool], width: usize) -> Regions {
+    check_shape(used, width);
+    let mut parent: Vec<usize> = (0..used.len()).collect();
+    for i in (0..used.len()).filter(|&i| used[i]) {
+        let up = i.checked_sub(width);
+        let left = Some(i).filter(|i| i % width > 0).map(|i| i - 1);
+        for n in IntoIterator::into_iter([up, left])
+            .flatten()
+            .filter(|&n| used[n])
+        {
+            let (a, b) = (find(&mut parent, i), find(&mut parent, n));
+            // the lower index wins, so every root is its set's first square
+            parent[a.max(b)] = a.min(b);
+        }
+    }
+    let mut regions = Regions::new(width, used.len());
+    let mut numbers = vec![None; used.len()];
+    for i in (0..used.len()).filter(|&i| used[i]) {
+        let root = find(&mut parent, i);
+        let region = *numbers[root].get_or_insert(regions.count());
+        regions.mark(i, region);
+    }
+    regions
+}
@@ -0,0 +1,119 @@
+//! Text in and out of `Op`s. The assembler understands everything in the
+//! puzzle input plus comments (`#` or `;` to the end of the line) and labels,
+//! which `jgz` can use in place of a relative offset:
+//!
+//! ```txt
+//! set i 31
+//! loop:
+//!     add i -1    # count down
+//!     jgz i loop
+//! ```
+
+use std::{collections::HashMap, fmt};
+
+use super::{Arg, Op};
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct AsmError {
+    /// counted from 1, like an editor would
+    pub line: usize,
+    pub msg: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.msg)
+    }
+}
+
+/// Single letters are taken by the registers
+fn is_label(token: &str) -> bool {
+    let mut chars = token.chars();
+    let first = chars
+        .next()
+        .filter(|c| c.is_ascii_alphabetic() || *c == '_');
+    first.is_some() && token.len() > 1 && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+pub fn assemble(source: &str) -> Result<Vec<Op>, AsmError> {
+    // first pass: find where every label points
+    let mut labels: HashMap<&str, usize> = HashMap::new();
+    let mut instrs: Vec<(usize, &str)> = Vec::new();
+    for (i, line) in source.lines().enumerate() {
+        let err = |msg| AsmError { line: i + 1, msg };
+        let mut line = line.split(['#', ';']).next().unwrap().trim();
+        while let Some((label, rest)) = line.split_once(':') {
+            let label = label.trim();
+            if !is_label(label) {
+                return Err(err(format!("bad label: {}", label)));
+            }
+            if labels.insert(label, instrs.len()).is_some() {
+                return Err(err(format!("duplicate label: {}", label)));
+            }
+            line = rest.trim();
+        }
+        if !line.is_empty() {
+            instrs.push((i + 1, line));
+        }
+    }
+    // second pass: swap labels for offsets and parse
+    instrs
+        .iter()
+        .enumerate()
+        .map(|(pos, &(line, text))| {
+            let err = |msg| AsmError { line, msg };
+            let tokens: Vec<_> = text.split_ascii_whitespace().collect();
+            let text = match tokens[..] {
+                ["jgz", cond, target] if is_label(target) => {
+                    let to = labels
+                        .get(target)
+                        .ok_or_else(|| err(format!("unknown label: {}", target)))?;
+                    format!("jgz {} {}", cond, *to as i64 - pos as i64)
+                }
+                _ => text.to_string(),
+            };
+            text.parse().map_err(err)
+        })
+        .collect()
+}
+
+/// One instruction per line, exactly as the puzzle input writes them. With
+/// `labels`, every fixed `jgz` that lands inside the program (or just past the
+/// end) jumps to a label instead.
+pub fn disassemble(program: &[Op], labels: bool) -> String {
+    if !labels {
+        let lines: Vec<_> = program.iter().map(Op::to_string).collect();
+        return lines.join("\n");
+    }
+    let target = |pos: usize, op: &Op| match *op {
+        Op::Jgz(_, Arg::Val(offset)) => {
+            let to = pos as i64 + offset;
+            Some(to as usize).filter(|_| (0..=program.len() as i64).contains(&to))
+        }
+        _ => None,
+    };
+    let mut targets: Vec<usize> = program
+        .iter()
+        .enumerate()
+        .filter_map(|(pos, op)| target(pos, op))
+        .collect();
+    targets.sort_unstable();
+    targets.dedup();
+    let name = |to: usize| format!("l{}", targets.binary_search(&to).unwrap());
+    let mut lines = Vec::new();
+    for pos in 0..=program.len() {
+        if targets.binary_search(&pos).is_ok() {
+            lines.push(format!("{}:", name(pos)));
+        }
+        let op = match program.get(pos) {
+            Some(op) => op,
+            None => break,
+        };
+        let line = match (op, target(pos, op)) {
+            (Op::Jgz(cond, _), Some(to)) => format!("jgz {} {}", cond, name(to)),
+            _ => op.to_string(),
+        };
+        lines.push(format!("    {}", line));
+    }
+    lines.join("\n")
+}
@@ -0,0 +1,395 @@
+//! Static analysis of Duet programs: basic blocks, the control-flow graph
+//! between them, the loops in it, and a rough decompilation back into
+//! structured pseudo-code.
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    convert::TryFrom,
+};
+
+use super::{reg_name, Arg, Op};
+
+/// Where control can go when a block finishes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Edge {
+    /// on to the next block, either in order or because a `jgz` wasn't taken
+    Next(usize),
+    /// a taken `jgz`
+    Jump(usize),
+    /// off either end of the program
+    Exit,
+    /// a `jgz` whose offset is in a register, so could land anywhere
+    Indirect,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Block {
+    /// instructions `start..end`
+    pub start: usize,
+    pub end: usize,
+    pub succs: Vec<Edge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Loop {
+    pub header: usize,
+    /// every block in the loop, header included, in order
+    pub body: Vec<usize>,
+}
+
+/// Where a `jgz` with a fixed offset lands, if it has one
+fn jump_target(pos: usize, op: &Op) -> Option<i64> {
+    match op {
+        Op::Jgz(_, Arg::Val(offset)) => Some(pos as i64 + offset),
+        _ => None,
+    }
+}
+
+/// A `jgz` on a positive number is really a `goto`
+fn always_jumps(op: &Op) -> bool {
+    matches!(op, Op::Jgz(Arg::Val(c), _) if *c > 0)
+}
+
+fn never_jumps(op: &Op) -> bool {
+    matches!(op, Op::Jgz(Arg::Val(c), _) if *c <= 0)
+}
+
+#[derive(Debug)]
+pub struct Cfg<'a> {
+    pub program: &'a [Op],
+    pub blocks: Vec<Block>,
+}
+
+impl<'a> Cfg<'a> {
+    pub fn build(program: &'a [Op]) -> Cfg<'a> {
+        let len = program.len();
+        let mut leaders = BTreeSet::new();
+        leaders.insert(0);
+        for (pos, op) in program.iter().enumerate() {
+            if let Op::Jgz(..) = op {
+                leaders.insert(pos + 1);
+            }
+            if let Some(to) = jump_target(pos, op).filter(|to| (0..len as i64).contains(to)) {
+                leaders.insert(to as usize);
+            }
+        }
+        let starts: Vec<usize> = leaders.into_iter().filter(|&s| s < len).collect();
+        let block_at: HashMap<usize, usize> =
+            starts.iter().enumerate().map(|(i, &s)| (s, i)).collect();
+        let edge_to = |pos: i64, edge: fn(usize) -> Edge| match usize::try_from(pos) {
+            Ok(pos) if pos < len => edge(block_at[&pos]),
+            _ => Edge::Exit,
+        };
+        let blocks = starts
+            .iter()
+            .enumerate()
+            .map(|(i, &start)| {
+                let end = starts.get(i + 1).cloned().unwrap_or(len);
+                let last = &program[end - 1];
+                let mut succs = Vec::new();
+                if !always_jumps(last) {
+                    succs.push(edge_to(end as i64, Edge::Next));
+                }
+                if !never_jumps(last) {
+                    match jump_target(end - 1, last) {
+                        Some(to) => succs.push(edge_to(to, Edge::Jump)),
+                        None if matches!(last, Op::Jgz(..)) => succs.push(Edge::Indirect),
+                        None => {}
+                    }
+                }
+                Block { start, end, succs }
+            })
+            .collect();
+        Cfg { program, blocks }
+    }
+
+    fn preds(&self) -> Vec<Vec<usize>> {
+        let mut preds = vec![Vec::new(); self.blocks.len()];
+        for (i, block) in self.blocks.iter().enumerate() {
+            for edge in &block.succs {
+                if let Edge::Next(j) | Edge::Jump(j) = edge {
+                    preds[*j].push(i);
+                }
+            }
+        }
+        preds
+    }
+
+    /// Which blocks can be got to from the entry. Indirect jumps are ignored,
+    /// so anything only they could land on counts as unreachable.
+    fn reachable(&self) -> Vec<bool> {
+        let mut seen = vec![false; self.blocks.len()];
+        let mut stack = vec![0];
+        while let Some(b) = stack.pop() {
+            if b >= seen.len() || seen[b] {
+                continue;
+            }
+            seen[b] = true;
+            for edge in &self.blocks[b].succs {
+                if let Edge::Next(j) | Edge::Jump(j) = edge {
+                    stack.push(*j);
+                }
+            }
+        }
+        seen
+    }
+
+    /// `dom[b][a]` is true when every path from the entry to `b` goes through
+    /// `a`. Indirect jumps are ignored, since there's no telling where they go,
+    /// and blocks that can't be reached aren't dominated by anything.
+    fn dominators(&self) -> Vec<Vec<bool>> {
+        let n = self.blocks.len();
+        if n == 0 {
+            return Vec::new();
+        }
+        let reachable = self.reachable();
+        let preds = self.preds();
+        let mut dom: Vec<Vec<bool>> = reachable.iter().map(|&r| vec![r; n]).collect();
+        dom[0] = (0..n).map(|i| i == 0).collect();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for b in (1..n).filter(|&b| reachable[b]) {
+                let mut new: Vec<bool> = (0..n)
+                    .map(|a| {
+                        preds[b]
+                            .iter()
+                            .filter(|&&p| reachable[p])
+                            .all(|&p| dom[p][a])
+                    })
+                    .collect();
+                new[b] = true;
+                if new != dom[b] {
+                    dom[b] = new;
+                    changed = true;
+                }
+            }
+        }
+        dom
+    }
+
+    /// Natural loops: one for each block that some later block jumps back to
+    /// while being unable to get there any other way
+    pub fn loops(&self) -> Vec<Loop> {
+        let dom = self.dominators();
+        let preds = self.preds();
+        let mut loops: BTreeMap<usize, BTreeSet<usize>> = BTreeMap::new();
+        for (tail, block) in self.blocks.iter().enumerate() {
+            for edge in &block.succs {
+                let header = match edge {
+                    Edge::Next(h) | Edge::Jump(h) if dom[tail][*h] => *h,
+                    _ => continue,
+                };
+                // everything that reaches the tail without passing the header
+                let body = loops.entry(header).or_default();
+                body.insert(header);
+                let mut stack = vec![tail];
+                while let Some(b) = stack.pop() {
+                    if body.insert(b) {
+                        stack.extend(&preds[b]);
+                    }
+                }
+            }
+        }
+        loops
+            .into_iter()
+            .map(|(header, body)| Loop {
+                header,
+                body: body.into_iter().collect(),
+            })
+            .collect()
+    }
+
+    /// Graphviz source, one box per block
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph duet {\n    node [shape=box, fontname=monospace];\n");
+        for (i, block) in self.blocks.iter().enumerate() {
+            let text: String = (block.start..block.end)
+                .map(|pos| format!("{}: {}\\l", pos, self.program[pos]))
+                .collect();
+            out += &format!("    b{} [label=\"{}\"];\n", i, text);
+        }
+        let (mut exit, mut indirect) = (false, false);
+        for (i, block) in self.blocks.iter().enumerate() {
+            let cond = match &self.program[block.end - 1] {
+                op if always_jumps(op) => String::new(),
+                Op::Jgz(a, _) => format!("{} > 0", a),
+                _ => String::new(),
+            };
+            for edge in &block.succs {
+                let (to, label) = match edge {
+                    Edge::Next(j) if cond.is_empty() => (format!("b{}", j), String::new()),
+                    Edge::Next(j) => (format!("b{}", j), String::from("else")),
+                    Edge::Jump(j) => (format!("b{}", j), cond.clone()),
+                    Edge::Exit => {
+                        exit = true;
+                        (String::from("exit"), String::new())
+                    }
+                    Edge::Indirect => {
+                        indirect = true;
+                        (String::from("indirect"), cond.clone())
+                    }
+                };
+                out += &format!("    b{} -> {} [label=\"{}\"];\n", i, to, label);
+            }
+        }
+        if exit {
+            out += "    exit [shape=doublecircle, label=\"\"];\n";
+        }
+        if indirect {
+            out += "    indirect [shape=diamond, label=\"?\"];\n";
+        }
+        out += "}\n";
+        out
+    }
+}
+
+/// Statements that don't affect control flow
+fn statement(op: &Op) -> String {
+    match op {
+        Op::Snd(a) => format!("send({})", a),
+        Op::Rcv(r) => format!("{} = receive()", reg_name(*r)),
+        Op::Set(r, a) => format!("{} = {}", reg_name(*r), a),
+        Op::Add(r, Arg::Val(v)) if *v < 0 => format!("{} -= {}", reg_name(*r), -v),
+        Op::Add(r, a) => format!("{} += {}", reg_name(*r), a),
+        Op::Mul(r, a) => format!("{} *= {}", reg_name(*r), a),
+        Op::Mod(r, a) => format!("{} %= {}", reg_name(*r), a),
+        Op::Jgz(..) => unreachable!("jumps are handled by the decompiler"),
+    }
+}
+
+struct Decompiler<'a> {
+    program: &'a [Op],
+    /// (instruction the line came from, nesting depth, text)
+    lines: Vec<(Option<usize>, usize, String)>,
+    gotos: BTreeSet<usize>,
+}
+
+impl Decompiler<'_> {
+    fn emit(&mut self, pos: Option<usize>, depth: usize, text: String) {
+        self.lines.push((pos, depth, text));
+    }
+
+    /// a jump that doesn't fit the structure being built
+    fn goto(&mut self, pos: usize, depth: usize, to: i64) {
+        let len = self.program.len() as i64;
+        let text = if (0..=len).contains(&to) {
+            self.gotos.insert(to as usize);
+            format!("goto L{}", to)
+        } else {
+            String::from("halt")
+        };
+        match &self.program[pos] {
+            op if always_jumps(op) => self.emit(Some(pos), depth, text),
+            Op::Jgz(a, _) => self.emit(Some(pos), depth, format!("if {} > 0 {{ {} }}", a, text)),
+            _ => unreachable!(),
+        }
+    }
+
+    /// the last back edge in `lo..hi` pointing at `pos`, if any
+    fn loop_end(&self, pos: usize, hi: usize) -> Option<usize> {
+        (pos..hi)
+            .rev()
+            .filter(|&j| !never_jumps(&self.program[j]))
+            .find(|&j| jump_target(j, &self.program[j]) == Some(pos as i64))
+    }
+
+    fn structure(&mut self, lo: usize, hi: usize, depth: usize) {
+        let mut pos = lo;
+        while pos < hi {
+            let op = self.program[pos];
+            if let Some(end) = self.loop_end(pos, hi) {
+                let cond = op_cond(&self.program[end]).unwrap();
+                self.emit(Some(pos), depth, String::from("do {"));
+                // the header itself might be the back edge, as in `jgz a 0`
+                if end > pos {
+                    self.structure(pos, end, depth + 1);
+                }
+                self.emit(Some(end), depth, format!("}} while {}", cond));
+                pos = end + 1;
+                continue;
+            }
+            let to = match op {
+                Op::Jgz(ref a, Arg::Reg(r)) => {
+                    let text = format!("if {} > 0 {{ jump by {} }}", a, reg_name(r));
+                    self.emit(Some(pos), depth, text);
+                    pos += 1;
+                    continue;
+                }
+                Op::Jgz(_, Arg::Val(offset)) => pos as i64 + offset,
+                _ => {
+                    self.emit(Some(pos), depth, statement(&op));
+                    pos += 1;
+                    continue;
+                }
+            };
+            if never_jumps(&op) {
+                self.emit(Some(pos), depth, String::from("nop"));
+                pos += 1;
+                continue;
+            }
+            // a conditional jump forward within the region skips what's
+            // between, so the skipped part runs only if the condition is false
+            if always_jumps(&op) || to <= pos as i64 + 1 || to > hi as i64 {
+                self.goto(pos, depth, to);
+                pos += 1;
+                continue;
+            }
+            let to = to as usize;
+            let cond = op_cond(&op).unwrap();
+            self.emit(Some(pos), depth, format!("if !({}) {{", cond));
+            let last = &self.program[to - 1];
+            match jump_target(to - 1, last) {
+                // ...and if the skipped part ends by jumping further forward,
+                // what it jumps over is the else branch
+                Some(end) if always_jumps(last) && end > to as i64 && end <= hi as i64 => {
+                    let end = end as usize;
+                    self.structure(pos + 1, to - 1, depth + 1);
+                    self.emit(Some(to - 1), depth, String::from("} else {"));
+                    self.structure(to, end, depth + 1);
+                    pos = end;
+                }
+                _ => {
+                    self.structure(pos + 1, to, depth + 1);
+                    pos = to;
+                }
+            }
+            self.emit(None, depth, String::from("}"));
+        }
+    }
+}
+
+fn op_cond(op: &Op) -> Option<String> {
+    match op {
+        _ if always_jumps(op) => Some(String::from("true")),
+        Op::Jgz(a, _) => Some(format!("{} > 0", a)),
+        _ => None,
+    }
+}
+
+/// Structured pseudo-code for the program. Loops become `do { } while`,
+/// forward jumps become `if` (and `else`), and anything that doesn't nest
+/// falls back to a labelled `goto`.
+pub fn decompile(program: &[Op]) -> String {
+    let mut decompiler = Decompiler {
+        program,
+        lines: Vec::new(),
+        gotos: BTreeSet::new(),
+    };
+    decompiler.structure(0, program.len(), 0);
+    let Decompiler { lines, gotos, .. } = decompiler;
+    let mut out = Vec::new();
+    let mut labelled = BTreeSet::new();
+    for (pos, depth, text) in lines {
+        if let Some(pos) = pos.filter(|p| gotos.contains(p)) {
+            if labelled.insert(pos) {
+                out.push(format!("L{}:", pos));
+            }
+        }
+        out.push(format!("{}{}", "    ".repeat(depth), text));
+    }
+    if gotos.contains(&program.len()) {
+        out.push(format!("L{}:", program.len()));
+    }
+    out.join("\n")
+}
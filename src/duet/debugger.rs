@@ -0,0 +1,331 @@
+use std::{
+    fmt,
+    fs::File,
+    io::{self, BufWriter, Write},
+    path::Path,
+};
+
+use super::{fmt_regs, reg_name, Comp, Device};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmp {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+    Neq,
+}
+
+impl Cmp {
+    fn eval(&self, a: i64, b: i64) -> bool {
+        match self {
+            Cmp::Lt => a < b,
+            Cmp::Lte => a <= b,
+            Cmp::Gt => a > b,
+            Cmp::Gte => a >= b,
+            Cmp::Eq => a == b,
+            Cmp::Neq => a != b,
+        }
+    }
+
+    fn parse(input: &str) -> Option<Cmp> {
+        match input {
+            "<" => Some(Cmp::Lt),
+            "<=" => Some(Cmp::Lte),
+            ">" => Some(Cmp::Gt),
+            ">=" => Some(Cmp::Gte),
+            "==" => Some(Cmp::Eq),
+            "!=" => Some(Cmp::Neq),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Cmp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Cmp::Lt => "<",
+            Cmp::Lte => "<=",
+            Cmp::Gt => ">",
+            Cmp::Gte => ">=",
+            Cmp::Eq => "==",
+            Cmp::Neq => "!=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// stop before executing the instruction at this index
+    Pc(usize),
+    /// stop when the register comes to compare true against the value, but
+    /// not again until it has been false in between
+    Reg(usize, Cmp, i64),
+}
+
+impl Breakpoint {
+    /// either a bare instruction index (`12`) or a register condition
+    /// (`a > 5`)
+    pub fn parse(input: &str) -> Result<Breakpoint, String> {
+        let tokens: Vec<_> = input.split_ascii_whitespace().collect();
+        match tokens[..] {
+            [pc] => pc
+                .parse()
+                .map(Breakpoint::Pc)
+                .map_err(|_| format!("not an instruction index: {}", pc)),
+            [reg, cmp, val] => {
+                let reg = match reg.as_bytes() {
+                    [c @ b'a'..=b'p'] => (c - b'a') as usize,
+                    _ => return Err(format!("not a register: {}", reg)),
+                };
+                let cmp = Cmp::parse(cmp).ok_or(format!("not a comparison: {}", cmp))?;
+                let val = val.parse().map_err(|_| format!("not a number: {}", val))?;
+                Ok(Breakpoint::Reg(reg, cmp, val))
+            }
+            _ => Err(format!("expected `<pc>` or `<reg> <cmp> <val>`: {}", input)),
+        }
+    }
+
+    fn holds<D: Device>(&self, comp: &Comp<D>) -> bool {
+        match *self {
+            Breakpoint::Pc(pc) => comp.pos == pc,
+            Breakpoint::Reg(reg, cmp, val) => cmp.eval(comp.regs[reg], val),
+        }
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Breakpoint::Pc(pc) => write!(f, "pc {}", pc),
+            Breakpoint::Reg(reg, cmp, val) => write!(f, "{} {} {}", reg_name(*reg), cmp, val),
+        }
+    }
+}
+
+/// Why a program handed control back to the debugger
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Stop {
+    /// index of the breakpoint that was hit
+    Breakpoint(usize),
+    /// waiting on a `rcv` with nothing to receive
+    Blocked,
+    /// the device has what it wanted, see `Device::halted`
+    Halted,
+    Terminated,
+}
+
+/// Wraps a single program, stepping it under the user's control
+pub struct Debugger<'a, D: Device> {
+    pub comp: Comp<'a, D>,
+    breakpoints: Vec<Breakpoint>,
+    /// whether each breakpoint held after the last step
+    held: Vec<bool>,
+    /// the first breakpoint the last step set off
+    hit: Option<usize>,
+    trace: Option<Box<dyn Write>>,
+}
+
+impl<'a, D: Device + fmt::Display> Debugger<'a, D> {
+    pub fn new(comp: Comp<'a, D>) -> Debugger<'a, D> {
+        Debugger {
+            comp,
+            breakpoints: Vec::new(),
+            held: Vec::new(),
+            hit: None,
+            trace: None,
+        }
+    }
+
+    pub fn breakpoints(&self) -> &[Breakpoint] {
+        &self.breakpoints
+    }
+
+    /// returns the index used to refer to the breakpoint later
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        self.breakpoints.push(breakpoint);
+        self.held.push(breakpoint.holds(&self.comp));
+        self.breakpoints.len() - 1
+    }
+
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        if index < self.breakpoints.len() {
+            self.held.remove(index);
+            Some(self.breakpoints.remove(index))
+        } else {
+            None
+        }
+    }
+
+    /// writes a line for every instruction executed from now on
+    pub fn trace_to(&mut self, out: Box<dyn Write>) {
+        self.trace = Some(out);
+    }
+
+    pub fn trace_to_file(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = File::create(path)?;
+        self.trace_to(Box::new(BufWriter::new(file)));
+        Ok(())
+    }
+
+    /// executes a single instruction, unless the program can't make progress.
+    /// Fails only if the trace can't be written.
+    pub fn step(&mut self) -> io::Result<Option<Stop>> {
+        if self.comp.terminated() {
+            return Ok(Some(Stop::Terminated));
+        }
+        if self.comp.device.halted() {
+            return Ok(Some(Stop::Halted));
+        }
+        let pos = self.comp.pos;
+        self.comp.step();
+        if self.comp.waiting {
+            return Ok(Some(Stop::Blocked));
+        }
+        if let Some(trace) = self.trace.as_mut() {
+            writeln!(
+                trace,
+                "{:>8} {:>4}  {:<12} {}",
+                self.comp.steps,
+                pos,
+                self.comp.program[pos].to_string(),
+                fmt_regs(&self.comp.regs)
+            )?;
+        }
+        self.hit = self.check();
+        if self.comp.terminated() {
+            return Ok(Some(Stop::Terminated));
+        }
+        if self.comp.device.halted() {
+            return Ok(Some(Stop::Halted));
+        }
+        Ok(None)
+    }
+
+    /// runs until a breakpoint is hit or the program can't go any further
+    pub fn cont(&mut self) -> io::Result<Stop> {
+        loop {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+            if let Some(index) = self.hit {
+                return Ok(Stop::Breakpoint(index));
+            }
+        }
+    }
+
+    /// Brings `held` up to date, returning the first breakpoint that went off.
+    /// Instruction breakpoints go off every time the program gets to them,
+    /// register ones only when they start to hold.
+    fn check(&mut self) -> Option<usize> {
+        let mut hit = None;
+        for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+            let was = self.held[i];
+            self.held[i] = breakpoint.holds(&self.comp);
+            let fired = match breakpoint {
+                Breakpoint::Pc(_) => self.held[i],
+                Breakpoint::Reg(..) => self.held[i] && !was,
+            };
+            if fired && hit.is_none() {
+                hit = Some(i);
+            }
+        }
+        hit
+    }
+
+    /// like `cont`, but ignores breakpoints
+    pub fn run_to_blocked(&mut self) -> io::Result<Stop> {
+        loop {
+            if let Some(stop) = self.step()? {
+                return Ok(stop);
+            }
+        }
+    }
+
+    pub fn dump(&self) -> String {
+        let op = match self.comp.program.get(self.comp.pos) {
+            Some(op) => op.to_string(),
+            None => String::from("<terminated>"),
+        };
+        format!(
+            "pc: {} ({})  steps: {}  waiting: {}\n{}\n{}",
+            self.comp.pos,
+            op,
+            self.comp.steps,
+            self.comp.waiting,
+            fmt_regs(&self.comp.regs),
+            self.comp.device
+        )
+    }
+
+    fn describe(&self, stop: Stop) -> String {
+        match stop {
+            Stop::Breakpoint(i) => format!(
+                "breakpoint {} ({}) at pc {}",
+                i, self.breakpoints[i], self.comp.pos
+            ),
+            Stop::Blocked => format!("blocked on rcv at pc {}", self.comp.pos),
+            Stop::Halted => format!("halted at pc {}\n{}", self.comp.pos, self.comp.device),
+            Stop::Terminated => format!("terminated after {} steps", self.comp.steps),
+        }
+    }
+
+    /// Runs one line of debugger input, returning what to show the user
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let (cmd, rest) = match line.trim().split_once(' ') {
+            Some((cmd, rest)) => (cmd, rest.trim()),
+            None => (line.trim(), ""),
+        };
+        match cmd {
+            "s" | "step" => {
+                let n = if rest.is_empty() {
+                    1
+                } else {
+                    rest.parse().map_err(|_| format!("not a count: {}", rest))?
+                };
+                for _ in 0..n {
+                    if let Some(stop) = self.step().map_err(|e| e.to_string())? {
+                        return Ok(self.describe(stop));
+                    }
+                }
+                Ok(self.dump())
+            }
+            "c" | "continue" => {
+                let stop = self.cont().map_err(|e| e.to_string())?;
+                Ok(self.describe(stop))
+            }
+            "r" | "run" => {
+                let stop = self.run_to_blocked().map_err(|e| e.to_string())?;
+                Ok(self.describe(stop))
+            }
+            "b" | "break" => {
+                let breakpoint = Breakpoint::parse(rest)?;
+                let i = self.add_breakpoint(breakpoint);
+                Ok(format!("breakpoint {}: {}", i, breakpoint))
+            }
+            "d" | "delete" => {
+                let i = rest
+                    .parse()
+                    .map_err(|_| format!("not an index: {}", rest))?;
+                self.remove_breakpoint(i)
+                    .map(|b| format!("deleted breakpoint {}: {}", i, b))
+                    .ok_or(format!("no breakpoint {}", i))
+            }
+            "l" | "list" => Ok(self
+                .breakpoints
+                .iter()
+                .enumerate()
+                .map(|(i, b)| format!("{}: {}", i, b))
+                .collect::<Vec<_>>()
+                .join("\n")),
+            "p" | "print" => Ok(self.dump()),
+            "trace" => {
+                self.trace_to_file(rest).map_err(|e| e.to_string())?;
+                Ok(format!("tracing to {}", rest))
+            }
+            _ => Err(format!("unknown command: {}", cmd)),
+        }
+    }
+}
@@ -0,0 +1,104 @@
+use std::{cell::RefCell, collections::VecDeque, fmt, rc::Rc};
+
+/// Whatever is on the other end of `snd` and `rcv`
+pub trait Device {
+    fn send(&mut self, val: i64);
+
+    /// Given the current value of the `rcv` register, returns the value it
+    /// should hold afterwards, or `None` if the program has to wait
+    fn receive(&mut self, val: i64) -> Option<i64>;
+
+    /// how many values are waiting to be received, if that means anything
+    fn pending(&self) -> Option<usize> {
+        None
+    }
+
+    /// whether the program has done what it was run for, so there's no point
+    /// going on even though it could
+    fn halted(&self) -> bool {
+        false
+    }
+}
+
+/// Part 1: `snd` plays a sound, `rcv` recovers the last one played
+#[derive(Debug, Default)]
+pub struct SoundCard {
+    pub last_played: Option<i64>,
+    pub recovered: Option<i64>,
+}
+
+impl Device for SoundCard {
+    fn send(&mut self, val: i64) {
+        self.last_played = Some(val);
+    }
+
+    fn receive(&mut self, val: i64) -> Option<i64> {
+        if val != 0 {
+            self.recovered = self.last_played;
+        }
+        Some(val)
+    }
+
+    /// part 1 only asks for the first sound recovered
+    fn halted(&self) -> bool {
+        self.recovered.is_some()
+    }
+}
+
+impl fmt::Display for SoundCard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "last played: {:?}, recovered: {:?}",
+            self.last_played, self.recovered
+        )
+    }
+}
+
+pub type Queue = Rc<RefCell<VecDeque<i64>>>;
+
+/// Part 2: `snd` and `rcv` talk to other programs through queues
+#[derive(Debug)]
+pub struct MessageQueue {
+    pub snd: Vec<Queue>,
+    pub rcv: Queue,
+}
+
+impl MessageQueue {
+    pub fn new(snd: Vec<Queue>, rcv: Queue) -> MessageQueue {
+        MessageQueue { snd, rcv }
+    }
+}
+
+impl Device for MessageQueue {
+    fn send(&mut self, val: i64) {
+        for queue in &self.snd {
+            queue.borrow_mut().push_front(val);
+        }
+    }
+
+    fn receive(&mut self, _: i64) -> Option<i64> {
+        self.rcv.borrow_mut().pop_back()
+    }
+
+    fn pending(&self) -> Option<usize> {
+        Some(self.rcv.borrow().len())
+    }
+}
+
+/// queues fill at the front and drain from the back, so print them back to
+/// front to show the next value to be received first
+fn fmt_queue(queue: &Queue) -> String {
+    let vals: Vec<_> = queue.borrow().iter().rev().map(i64::to_string).collect();
+    format!("[{}]", vals.join(", "))
+}
+
+impl fmt::Display for MessageQueue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "inbox: {}", fmt_queue(&self.rcv))?;
+        for (i, queue) in self.snd.iter().enumerate() {
+            write!(f, "\noutbox {}: {}", i, fmt_queue(queue))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,244 @@
+//! The "Duet" assembly from day 18: a handful of instructions operating on
+//! sixteen single-letter registers, with `snd` and `rcv` hooked up to some
+//! [`Device`].
+
+use std::{fmt, str::FromStr};
+
+pub mod asm;
+pub mod cfg;
+pub mod debugger;
+pub mod device;
+pub mod fast;
+pub mod network;
+pub mod profile;
+pub mod threaded;
+
+pub use debugger::{Breakpoint, Debugger, Stop};
+pub use device::{Device, MessageQueue, Queue, SoundCard};
+pub use network::{Network, Outcome, ProgramState, Report, Topology};
+pub use profile::Profile;
+pub use threaded::run_threaded;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg {
+    Val(i64),
+    Reg(usize),
+}
+
+/// A number or one of the sixteen registers
+impl FromStr for Arg {
+    type Err = String;
+
+    fn from_str(token: &str) -> Result<Arg, String> {
+        if let Ok(val) = token.parse() {
+            return Ok(Arg::Val(val));
+        }
+        match token.as_bytes() {
+            [c @ b'a'..=b'p'] => Ok(Arg::Reg((c - b'a') as usize)),
+            _ => Err(format!("not a register or a number: {}", token)),
+        }
+    }
+}
+
+impl fmt::Display for Arg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Val(v) => write!(f, "{}", v),
+            Arg::Reg(r) => write!(f, "{}", reg_name(*r)),
+        }
+    }
+}
+
+pub fn reg_name(reg: usize) -> char {
+    (b'a' + reg as u8) as char
+}
+
+pub fn fmt_regs(regs: &[i64; 16]) -> String {
+    regs.iter()
+        .enumerate()
+        .map(|(i, v)| format!("{}={}", reg_name(i), v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Snd(Arg),
+    Rcv(usize),
+    Set(usize, Arg),
+    Add(usize, Arg),
+    Mul(usize, Arg),
+    Mod(usize, Arg),
+    Jgz(Arg, Arg),
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Op, String> {
+        let tokens: Vec<_> = input.split_ascii_whitespace().collect();
+        let arity = match tokens.first() {
+            Some(&"snd") | Some(&"rcv") => 1,
+            Some(_) => 2,
+            None => return Err(String::from("empty instruction")),
+        };
+        if tokens.len() != arity + 1 {
+            return Err(format!("expected {} operand(s): {}", arity, input));
+        }
+        let arg = |i: usize| tokens[i].parse::<Arg>();
+        // `snd` and `jgz` may take a number where the others need a register
+        let reg = |i: usize| match arg(i)? {
+            Arg::Reg(r) => Ok(r),
+            Arg::Val(_) => Err(format!("expected a register: {}", tokens[i])),
+        };
+        match tokens[0] {
+            "snd" => Ok(Op::Snd(arg(1)?)),
+            "rcv" => Ok(Op::Rcv(reg(1)?)),
+            "set" => Ok(Op::Set(reg(1)?, arg(2)?)),
+            "add" => Ok(Op::Add(reg(1)?, arg(2)?)),
+            "mul" => Ok(Op::Mul(reg(1)?, arg(2)?)),
+            "mod" => Ok(Op::Mod(reg(1)?, arg(2)?)),
+            "jgz" => Ok(Op::Jgz(arg(1)?, arg(2)?)),
+            other => Err(format!("unidentified instruction: {}", other)),
+        }
+    }
+}
+
+impl Op {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Op::Snd(_) => "snd",
+            Op::Rcv(_) => "rcv",
+            Op::Set(..) => "set",
+            Op::Add(..) => "add",
+            Op::Mul(..) => "mul",
+            Op::Mod(..) => "mod",
+            Op::Jgz(..) => "jgz",
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Op::Snd(a) => write!(f, "snd {}", a),
+            Op::Rcv(r) => write!(f, "rcv {}", reg_name(*r)),
+            Op::Set(r, a) => write!(f, "set {} {}", reg_name(*r), a),
+            Op::Add(r, a) => write!(f, "add {} {}", reg_name(*r), a),
+            Op::Mul(r, a) => write!(f, "mul {} {}", reg_name(*r), a),
+            Op::Mod(r, a) => write!(f, "mod {} {}", reg_name(*r), a),
+            Op::Jgz(a, b) => write!(f, "jgz {} {}", a, b),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Comp<'a, D: Device> {
+    pub program: &'a [Op],
+    pub regs: [i64; 16],
+    pub pos: usize,
+    pub waiting: bool,
+    pub device: D,
+    pub snd_count: u64,
+    pub rcv_count: u64,
+    /// instructions executed so far
+    pub steps: u64,
+    /// only kept once `profile` has been called
+    pub profile: Option<Profile>,
+}
+
+impl<D: Device> Comp<'_, D> {
+    pub fn new(program: &[Op], id: i64, device: D) -> Comp<'_, D> {
+        let mut comp = Comp {
+            program,
+            regs: [0; 16],
+            pos: 0,
+            waiting: false,
+            device,
+            snd_count: 0,
+            rcv_count: 0,
+            steps: 0,
+            profile: None,
+        };
+        comp.regs['p' as usize - 'a' as usize] = id;
+        comp
+    }
+
+    pub fn get(&self, arg: &Arg) -> i64 {
+        match arg {
+            Arg::Val(v) => *v,
+            Arg::Reg(r) => self.regs[*r],
+        }
+    }
+
+    /// jumping off either end of the program terminates it
+    pub fn terminated(&self) -> bool {
+        self.pos >= self.program.len()
+    }
+
+    /// start counting what gets executed
+    pub fn profile(&mut self) {
+        self.profile = Some(Profile::new(self.program.len()));
+    }
+
+    pub fn step(&mut self) {
+        if self.terminated() {
+            return;
+        }
+        let pos = self.pos;
+        if let Some(profile) = self.profile.as_mut() {
+            let pending = self.device.pending().unwrap_or(0);
+            profile.high_water = profile.high_water.max(pending);
+        }
+        self.exec();
+        if !self.waiting {
+            self.steps += 1;
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            if self.waiting {
+                profile.stalls += 1;
+            } else {
+                profile.record(pos, &self.program[pos]);
+            }
+        }
+    }
+
+    fn exec(&mut self) {
+        match self.program[self.pos] {
+            Op::Snd(ref a) => {
+                self.device.send(self.get(a));
+                self.snd_count += 1;
+            }
+            Op::Rcv(r) => match self.device.receive(self.regs[r]) {
+                Some(val) => {
+                    self.regs[r] = val;
+                    self.waiting = false;
+                    self.rcv_count += 1;
+                }
+                None => {
+                    self.waiting = true;
+                    return;
+                }
+            },
+            Op::Set(r, ref a) => {
+                self.regs[r] = self.get(a);
+            }
+            Op::Add(r, ref a) => {
+                self.regs[r] += self.get(a);
+            }
+            Op::Mul(r, ref a) => {
+                self.regs[r] *= self.get(a);
+            }
+            Op::Mod(r, ref a) => {
+                self.regs[r] %= self.get(a);
+            }
+            Op::Jgz(ref a, ref b) => {
+                if self.get(a) > 0 {
+                    self.pos = (self.pos as i64 + self.get(b)) as usize;
+                    return;
+                }
+            }
+        }
+        self.pos += 1;
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt;
+
+use super::{fmt_regs, Comp, Device, MessageQueue, Op, Queue};
+
+/// Who hears a program's `snd`
+#[derive(Debug, Clone, Copy)]
+pub enum Topology {
+    /// each program sends to the next one, and the last to the first
+    Ring,
+    /// 0 and 1 talk to each other, as do 2 and 3, and so on. An odd one out
+    /// sends into the void.
+    Pairs,
+    /// each program sends to every other program
+    Broadcast,
+}
+
+impl Topology {
+    pub fn peers(&self, id: usize, n: usize) -> Vec<usize> {
+        match self {
+            Topology::Ring => vec![(id + 1) % n],
+            Topology::Pairs => Some(id ^ 1).filter(|&peer| peer < n).into_iter().collect(),
+            Topology::Broadcast => (0..n).filter(|&peer| peer != id).collect(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// every program ran off the end of its instructions
+    Terminated,
+    /// nothing is left running, and at least one program is stuck on a `rcv`
+    Deadlock,
+}
+
+/// Where a program ended up once the network stopped
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProgramState {
+    pub pos: usize,
+    pub regs: [i64; 16],
+    /// messages sent to it that it never picked up, next one first
+    pub pending: Vec<i64>,
+    pub steps: u64,
+    /// the `rcv` it's stuck on, if it didn't run off the end
+    pub blocked_on: Option<Op>,
+}
+
+impl ProgramState {
+    pub fn new<D: Device>(comp: &Comp<D>, pending: Vec<i64>) -> ProgramState {
+        ProgramState {
+            pos: comp.pos,
+            regs: comp.regs,
+            pending,
+            steps: comp.steps,
+            blocked_on: comp.program.get(comp.pos).cloned(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Report {
+    pub outcome: Outcome,
+    pub sent: Vec<u64>,
+    pub received: Vec<u64>,
+    pub programs: Vec<ProgramState>,
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{:?}", self.outcome)?;
+        for (id, state) in self.programs.iter().enumerate() {
+            match &state.blocked_on {
+                Some(op) => write!(f, "program {}: blocked on `{}` at pc {}", id, op, state.pos)?,
+                None => write!(f, "program {}: terminated at pc {}", id, state.pos)?,
+            }
+            writeln!(
+                f,
+                " after {} steps, sent {}, received {}",
+                state.steps, self.sent[id], self.received[id]
+            )?;
+            writeln!(f, "    {}", fmt_regs(&state.regs))?;
+            writeln!(f, "    pending: {:?}", state.pending)?;
+        }
+        Ok(())
+    }
+}
+
+/// Several copies of one program, each with its own inbox
+#[derive(Debug)]
+pub struct Network<'a> {
+    pub comps: Vec<Comp<'a, MessageQueue>>,
+}
+
+impl<'a> Network<'a> {
+    pub fn new(program: &'a [Op], n: usize, topology: Topology) -> Network<'a> {
+        let inboxes: Vec<Queue> = (0..n).map(|_| Queue::default()).collect();
+        let comps = (0..n)
+            .map(|id| {
+                let snd = topology
+                    .peers(id, n)
+                    .into_iter()
+                    .map(|peer| inboxes[peer].clone())
+                    .collect();
+                let device = MessageQueue::new(snd, inboxes[id].clone());
+                Comp::new(program, id as i64, device)
+            })
+            .collect();
+        Network { comps }
+    }
+
+    pub fn profile(&mut self) {
+        for comp in self.comps.iter_mut() {
+            comp.profile();
+        }
+    }
+
+    /// hot spots for every program that was profiled
+    pub fn profile_report(&self, top: usize) -> String {
+        let mut out = String::new();
+        for (id, comp) in self.comps.iter().enumerate() {
+            if let Some(profile) = &comp.profile {
+                out += &format!("program {}: {}", id, profile.report(comp.program, top));
+            }
+        }
+        out
+    }
+
+    /// a program is stuck when it can't make progress until someone else does
+    fn stuck(comp: &Comp<MessageQueue>) -> bool {
+        comp.terminated() || (comp.waiting && comp.device.rcv.borrow().is_empty())
+    }
+
+    pub fn run(&mut self) -> Report {
+        while !self.comps.iter().all(Network::stuck) {
+            for comp in self.comps.iter_mut() {
+                comp.step();
+            }
+        }
+        let outcome = if self.comps.iter().all(Comp::terminated) {
+            Outcome::Terminated
+        } else {
+            Outcome::Deadlock
+        };
+        Report {
+            outcome,
+            sent: self.comps.iter().map(|c| c.snd_count).collect(),
+            received: self.comps.iter().map(|c| c.rcv_count).collect(),
+            programs: self
+                .comps
+                .iter()
+                .map(|c| {
+                    let pending = c.device.rcv.borrow().iter().rev().cloned().collect();
+                    ProgramState::new(c, pending)
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::collections::BTreeMap;
+
+use super::Op;
+
+/// What one program spent its time on
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// executions of each instruction, by index
+    pub per_pc: Vec<u64>,
+    /// executions of each kind of instruction, by mnemonic
+    pub per_op: BTreeMap<&'static str, u64>,
+    pub steps: u64,
+    /// times the program tried to `rcv` and had to wait
+    pub stalls: u64,
+    /// most messages ever waiting to be received at once
+    pub high_water: usize,
+}
+
+impl Profile {
+    pub fn new(len: usize) -> Profile {
+        Profile {
+            per_pc: vec![0; len],
+            ..Profile::default()
+        }
+    }
+
+    pub fn record(&mut self, pos: usize, op: &Op) {
+        self.per_pc[pos] += 1;
+        *self.per_op.entry(op.name()).or_insert(0) += 1;
+        self.steps += 1;
+    }
+
+    /// The `top` busiest instructions, then the totals by opcode
+    pub fn report(&self, program: &[Op], top: usize) -> String {
+        let pct = |n: u64| 100.0 * n as f64 / self.steps.max(1) as f64;
+        let mut out = format!(
+            "{} steps, {} stalls, inbox high-water {}\n",
+            self.steps, self.stalls, self.high_water
+        );
+        let mut hot: Vec<_> = self.per_pc.iter().enumerate().collect();
+        hot.sort_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+        for (pos, &n) in hot.into_iter().take(top).filter(|(_, &n)| n > 0) {
+            let op = program[pos].to_string();
+            out += &format!("{:>4}  {:<14}{:>10} {:>6.2}%\n", pos, op, n, pct(n));
+        }
+        let mut ops: Vec<_> = self.per_op.iter().collect();
+        ops.sort_by(|(a, m), (b, n)| n.cmp(m).then(a.cmp(b)));
+        for (name, &n) in ops {
+            out += &format!("{:>4}  {:<14}{:>10} {:>6.2}%\n", "", name, n, pct(n));
+        }
+        out
+    }
+}
@@ -0,0 +1,143 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use super::{Comp, Device, Op, Outcome, ProgramState, Report, Topology};
+
+/// `None` tells a blocked program that everyone else is stuck too
+type Message = Option<i64>;
+
+/// Bookkeeping shared by the threads, so the last one to get stuck knows it
+#[derive(Debug)]
+struct Census {
+    /// messages sent to each program that it hasn't picked up yet
+    pending: Vec<usize>,
+    /// programs blocked on an empty inbox
+    waiting: Vec<bool>,
+    /// programs that are either waiting or finished
+    blocked: usize,
+    deadlock: bool,
+    halt: Vec<Sender<Message>>,
+}
+
+impl Census {
+    fn new(halt: Vec<Sender<Message>>) -> Census {
+        let n = halt.len();
+        Census {
+            pending: vec![0; n],
+            waiting: vec![false; n],
+            blocked: 0,
+            deadlock: false,
+            halt,
+        }
+    }
+
+    /// a program has started waiting or has finished
+    fn block(&mut self) {
+        self.blocked += 1;
+        if self.blocked == self.halt.len() && self.waiting.iter().any(|&w| w) {
+            self.deadlock = true;
+            for tx in &self.halt {
+                let _ = tx.send(None);
+            }
+        }
+    }
+}
+
+/// Part 2 again, but across real threads
+#[derive(Debug)]
+struct Channel {
+    id: usize,
+    snd: Vec<(usize, Sender<Message>)>,
+    rcv: Receiver<Message>,
+    census: Arc<Mutex<Census>>,
+}
+
+impl Device for Channel {
+    fn send(&mut self, val: i64) {
+        let mut census = self.census.lock().unwrap();
+        for (peer, tx) in &self.snd {
+            census.pending[*peer] += 1;
+            if census.waiting[*peer] {
+                census.waiting[*peer] = false;
+                census.blocked -= 1;
+            }
+            // a peer that has already finished has dropped its receiver
+            let _ = tx.send(Some(val));
+        }
+    }
+
+    fn receive(&mut self, _: i64) -> Option<i64> {
+        {
+            let mut census = self.census.lock().unwrap();
+            if census.pending[self.id] == 0 {
+                census.waiting[self.id] = true;
+                census.block();
+            }
+        }
+        let val = self.rcv.recv().unwrap()?;
+        self.census.lock().unwrap().pending[self.id] -= 1;
+        Some(val)
+    }
+}
+
+pub fn run_threaded(program: &[Op], n: usize, topology: Topology) -> Report {
+    let (txs, rxs): (Vec<_>, Vec<_>) = (0..n).map(|_| mpsc::channel()).unzip();
+    let census = Arc::new(Mutex::new(Census::new(txs.clone())));
+    let results: Vec<_> = thread::scope(|s| {
+        let handles: Vec<_> = rxs
+            .into_iter()
+            .enumerate()
+            .map(|(id, rcv)| {
+                let snd = topology
+                    .peers(id, n)
+                    .into_iter()
+                    .map(|peer| (peer, txs[peer].clone()))
+                    .collect();
+                let census = census.clone();
+                s.spawn(move || {
+                    let device = Channel {
+                        id,
+                        snd,
+                        rcv,
+                        census: census.clone(),
+                    };
+                    let mut comp = Comp::new(program, id as i64, device);
+                    while !comp.terminated() && !comp.waiting {
+                        comp.step();
+                    }
+                    if comp.terminated() {
+                        census.lock().unwrap().block();
+                    }
+                    let state = ProgramState::new(&comp, Vec::new());
+                    // hang on to the inbox so that nothing sent to a finished
+                    // program goes missing
+                    (comp.snd_count, comp.rcv_count, state, comp.device.rcv)
+                })
+            })
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+    let outcome = if census.lock().unwrap().deadlock {
+        Outcome::Deadlock
+    } else {
+        Outcome::Terminated
+    };
+    let mut report = Report {
+        outcome,
+        sent: Vec::new(),
+        received: Vec::new(),
+        programs: Vec::new(),
+    };
+    for (snd, rcv, mut state, inbox) in results {
+        state.pending = inbox.try_iter().flatten().collect();
+        report.sent.push(snd);
+        report.received.push(rcv);
+        report.programs.push(state);
+    }
+    report
+}
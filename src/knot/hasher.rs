@@ -0,0 +1,49 @@
+//! The knot hash behind `std::hash`, for trying out in a `HashMap`. It's far
+//! too slow for real use: every round goes back over all of the input, so
+//! nothing happens until the end and each `finish` hashes from scratch.
+
+use std::hash::{BuildHasherDefault, Hasher};
+
+use super::{KnotHash, ROUNDS, SUFFIX};
+
+/// Collects everything written to it and hashes it all at once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KnotHasher {
+    bytes: Vec<u8>,
+}
+
+impl KnotHasher {
+    pub fn new() -> KnotHasher {
+        KnotHasher::default()
+    }
+
+    /// The same as hashing everything written so far as one string
+    pub fn digest(&self) -> KnotHash {
+        let lengths: Vec<_> = self
+            .bytes
+            .iter()
+            .map(|&b| usize::from(b))
+            .chain(SUFFIX.iter().cloned())
+            .collect();
+        KnotHash::sparse(&lengths, ROUNDS).expect("bytes and the suffix always fit")
+    }
+
+    pub fn finish128(&self) -> u128 {
+        self.digest().dense()
+    }
+}
+
+impl Hasher for KnotHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        self.bytes.extend_from_slice(bytes);
+    }
+
+    /// The low half of `finish128`
+    fn finish(&self) -> u64 {
+        self.finish128() as u64
+    }
+}
+
+/// For `HashMap::with_hasher`. Keep in mind that `Hash` adds its own bytes
+/// (a `str` is followed by `0xff`), so keys don't hash the same as their text.
+pub type BuildKnotHasher = BuildHasherDefault<KnotHasher>;
@@ -0,0 +1,201 @@
+//! The knot hash from days 10 and 14: a ring of 256 marks, twisted by
+//! reversing runs of it, then folded down to 16 bytes. `KnotHashParams`
+//! covers other sizes, like the 5 mark ring in the day 10 example.
+
+mod hasher;
+
+use std::{
+    convert::{Infallible, TryInto},
+    str::FromStr,
+};
+
+pub use hasher::{BuildKnotHasher, KnotHasher};
+
+/// Appended to the input before hashing it
+pub const SUFFIX: [usize; 5] = [17, 31, 73, 47, 23];
+pub const ROUNDS: usize = 64;
+
+/// Reverses the `length` marks starting at `pos`, wrapping around the end.
+/// Swaps from both ends in towards the middle, so nothing is allocated.
+/// Panics if `length` is more than the whole ring.
+pub fn twist(pos: usize, length: usize, state: &mut [u8]) {
+    let n = state.len();
+    assert!(
+        length <= n,
+        "length {} is longer than the ring of {}",
+        length,
+        n
+    );
+    if length < 2 {
+        return;
+    }
+    let (mut i, mut j) = (pos % n, (pos + length - 1) % n);
+    for _ in 0..length / 2 {
+        state.swap(i, j);
+        i = if i + 1 == n { 0 } else { i + 1 };
+        j = if j == 0 { n - 1 } else { j - 1 };
+    }
+}
+
+/// XOR of one block of the sparse hash
+pub fn dense_hash(block: &[u8]) -> u8 {
+    block.iter().fold(0, |a, b| a ^ b)
+}
+
+/// The ring part way through (or done with) hashing. Each round picks up
+/// where the last one left off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnotHash {
+    pub state: Vec<u8>,
+    pub pos: usize,
+    pub skip: usize,
+    /// how many marks go into each byte of the dense hash
+    pub block: usize,
+}
+
+impl Default for KnotHash {
+    fn default() -> KnotHash {
+        KnotHash::new(256, 16)
+    }
+}
+
+impl KnotHash {
+    /// A fresh ring of `size` marks, numbered from 0. See
+    /// `KnotHashParams::validate` for what makes sense.
+    pub fn new(size: usize, block: usize) -> KnotHash {
+        KnotHash {
+            state: (0..size).map(|i| i as u8).collect(),
+            pos: 0,
+            skip: 0,
+            block,
+        }
+    }
+
+    /// `rounds` rounds of `lengths` exactly as given, without the suffix, on
+    /// the usual ring of 256. One round is day 10 part 1.
+    pub fn sparse(lengths: &[usize], rounds: usize) -> Result<KnotHash, String> {
+        let params = KnotHashParams {
+            rounds,
+            suffix: Vec::new(),
+            ..KnotHashParams::default()
+        };
+        params.sparse(lengths)
+    }
+
+    /// Panics if any of `lengths` is longer than the ring
+    pub fn round(&mut self, lengths: &[usize]) {
+        for &length in lengths {
+            twist(self.pos, length, &mut self.state);
+            self.pos = (self.pos + length + self.skip) % self.state.len();
+            self.skip += 1;
+        }
+    }
+
+    /// The first two marks multiplied, the answer to day 10 part 1
+    pub fn product(&self) -> u32 {
+        self.state[0] as u32 * self.state[1] as u32
+    }
+
+    /// One byte per block, however many that makes
+    pub fn digest(&self) -> Vec<u8> {
+        self.state.chunks(self.block).map(dense_hash).collect()
+    }
+
+    /// Panics unless there are exactly 16 blocks, as in the real thing
+    pub fn to_bytes(&self) -> [u8; 16] {
+        self.digest()
+            .try_into()
+            .expect("the dense hash isn't 16 bytes")
+    }
+
+    /// All 128 bits, the first byte highest, so bit 127 is the one that comes
+    /// first in `to_hex`
+    pub fn dense(&self) -> u128 {
+        u128::from_be_bytes(self.to_bytes())
+    }
+
+    pub fn to_hex(&self) -> String {
+        self.digest().iter().map(|b| format!("{:02x}", b)).collect()
+    }
+}
+
+/// The full hash of some text: its bytes plus `SUFFIX`, for `ROUNDS` rounds
+impl FromStr for KnotHash {
+    type Err = Infallible;
+
+    fn from_str(input: &str) -> Result<KnotHash, Infallible> {
+        let lengths: Vec<_> = input
+            .bytes()
+            .map(usize::from)
+            .chain(SUFFIX.iter().cloned())
+            .collect();
+        Ok(KnotHash::sparse(&lengths, ROUNDS).expect("bytes and the suffix always fit"))
+    }
+}
+
+/// Everything about the hash that the puzzle fixes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnotHashParams {
+    /// marks on the ring, which are numbered with bytes so 256 at most
+    pub size: usize,
+    pub rounds: usize,
+    /// added to the end of every set of lengths
+    pub suffix: Vec<usize>,
+    /// marks per byte of the dense hash; has to divide `size`
+    pub block: usize,
+}
+
+impl Default for KnotHashParams {
+    /// The ones from the puzzle
+    fn default() -> KnotHashParams {
+        KnotHashParams {
+            size: 256,
+            rounds: ROUNDS,
+            suffix: SUFFIX.to_vec(),
+            block: 16,
+        }
+    }
+}
+
+impl KnotHashParams {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=256).contains(&self.size) {
+            return Err(format!("ring size {} isn't from 1 to 256", self.size));
+        }
+        if self.block == 0 || !self.size.is_multiple_of(self.block) {
+            return Err(format!(
+                "block of {} doesn't divide the ring of {}",
+                self.block, self.size
+            ));
+        }
+        self.check(&self.suffix)
+    }
+
+    fn check(&self, lengths: &[usize]) -> Result<(), String> {
+        match lengths.iter().find(|&&length| length > self.size) {
+            Some(length) => Err(format!(
+                "length {} is longer than the ring of {}",
+                length, self.size
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// `lengths` then the suffix, for however many rounds
+    pub fn sparse(&self, lengths: &[usize]) -> Result<KnotHash, String> {
+        self.validate()?;
+        self.check(lengths)?;
+        let lengths: Vec<_> = lengths.iter().chain(&self.suffix).cloned().collect();
+        let mut hash = KnotHash::new(self.size, self.block);
+        for _ in 0..self.rounds {
+            hash.round(&lengths);
+        }
+        Ok(hash)
+    }
+
+    /// The bytes of `input` as lengths
+    pub fn hash(&self, input: &str) -> Result<KnotHash, String> {
+        let lengths: Vec<_> = input.bytes().map(usize::from).collect();
+        self.sparse(&lengths)
+    }
+}
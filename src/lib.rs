@@ -0,0 +1,5 @@
+pub mod disk;
+pub mod duet;
+pub mod knot;
+pub mod registers;
+pub mod stream;
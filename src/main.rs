@@ -1,3 +1,188 @@
+use std::{
+    env, fmt, fs,
+    io::{self, BufRead, Write},
+    process,
+};
+
+use aoc_17::{
+    duet::{asm, Comp, Debugger, Device, MessageQueue, Queue, SoundCard},
+    knot::KnotHashParams,
+    registers::Session,
+};
+
+const USAGE: &str = "\
+usage: aoc-17 duet <file> [--part 1|2] [--id N] [--trace FILE]
+       aoc-17 registers [file]
+       aoc-17 knot-hash [--rounds N] [--lengths a,b,c] [--size 256] [--block 16]
+                         [--sparse] [--product] [string]";
+
 fn main() {
-    println!("Check the \"tests\" folder for the solutions");
+    let args: Vec<String> = env::args().skip(1).collect();
+    match args.first().map(String::as_str) {
+        Some("duet") => duet(&args[1..]),
+        Some("registers") => registers(&args[1..]),
+        Some("knot-hash") => knot_hash(&args[1..]),
+        Some(_) => fail(USAGE),
+        None => println!("Check the \"tests\" folder for the solutions"),
+    }
+}
+
+fn fail(msg: &str) -> ! {
+    eprintln!("{}", msg);
+    process::exit(1)
+}
+
+/// Steps through a day 18 program interactively. In part 2 mode the program's
+/// inbox is filled by hand with `push <val>`.
+fn duet(args: &[String]) {
+    let mut path = None;
+    let (mut part, mut id, mut trace) = (1, 0, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "--part" => part = value().parse().unwrap_or_else(|_| fail(USAGE)),
+            "--id" => id = value().parse().unwrap_or_else(|_| fail(USAGE)),
+            "--trace" => trace = Some(value()),
+            _ => path = Some(arg.clone()),
+        }
+    }
+    let path = path.unwrap_or_else(|| fail(USAGE));
+    let source = fs::read_to_string(&path).unwrap_or_else(|e| fail(&e.to_string()));
+    let program = asm::assemble(&source).unwrap_or_else(|e| fail(&e.to_string()));
+    match part {
+        1 => {
+            let comp = Comp::new(&program, id, SoundCard::default());
+            debug(Debugger::new(comp), trace, |_, _| None);
+        }
+        2 => {
+            let inbox = Queue::default();
+            let device = MessageQueue::new(vec![Queue::default()], inbox.clone());
+            let comp = Comp::new(&program, id, device);
+            debug(Debugger::new(comp), trace, |cmd, rest| match cmd {
+                "push" => Some(match rest.parse() {
+                    Ok(val) => {
+                        inbox.borrow_mut().push_front(val);
+                        Ok(format!("pushed {}", val))
+                    }
+                    Err(_) => Err(format!("not a number: {}", rest)),
+                }),
+                _ => None,
+            });
+        }
+        _ => fail(USAGE),
+    }
+}
+
+/// `extra` gets first go at each command, for anything device-specific
+fn debug<D, F>(mut debugger: Debugger<D>, trace: Option<String>, mut extra: F)
+where
+    D: Device + fmt::Display,
+    F: FnMut(&str, &str) -> Option<Result<String, String>>,
+{
+    if let Some(path) = trace {
+        debugger
+            .trace_to_file(path)
+            .unwrap_or_else(|e| fail(&e.to_string()));
+    }
+    println!("{}", debugger.dump());
+    repl("(duet) ", |line| {
+        let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
+        extra(cmd, rest.trim()).unwrap_or_else(|| debugger.command(line))
+    });
+}
+
+/// Runs day 8 instructions as they're typed, after the optional file
+fn registers(args: &[String]) {
+    let mut session = Session::new();
+    match args {
+        [] => (),
+        [path] => match session.load_file(path) {
+            Ok(out) => println!("{}", out),
+            Err(err) => fail(&err),
+        },
+        _ => fail(USAGE),
+    }
+    repl("(registers) ", |line| session.command(line));
+}
+
+/// Prints the knot hash of a string, as in day 10 part 2. With `--lengths`
+/// those are used as they are, and there's just the one round unless told
+/// otherwise, as in part 1.
+fn knot_hash(args: &[String]) {
+    let (mut input, mut lengths, mut rounds, mut block) = (None, None, None, None);
+    let mut params = KnotHashParams::default();
+    let (mut sparse, mut product) = (false, false);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().cloned().unwrap_or_else(|| fail(USAGE));
+        match arg.as_str() {
+            "--rounds" => rounds = Some(value().parse().unwrap_or_else(|_| fail(USAGE))),
+            "--lengths" => {
+                let list: Result<Vec<usize>, _> =
+                    value().split(',').map(|n| n.trim().parse()).collect();
+                lengths = Some(list.unwrap_or_else(|_| fail(USAGE)));
+            }
+            "--size" => params.size = value().parse().unwrap_or_else(|_| fail(USAGE)),
+            "--block" => block = Some(value().parse().unwrap_or_else(|_| fail(USAGE))),
+            "--sparse" => sparse = true,
+            "--product" => product = true,
+            _ => input = Some(arg.clone()),
+        }
+    }
+    // 16 bytes of digest if the ring allows it, otherwise just the one
+    params.block = block.unwrap_or(if params.size.is_multiple_of(16) {
+        params.size / 16
+    } else {
+        params.size
+    });
+    let hash = match (input, lengths) {
+        (Some(input), None) => {
+            params.rounds = rounds.unwrap_or(params.rounds);
+            params.hash(&input)
+        }
+        (None, Some(lengths)) => {
+            params.rounds = rounds.unwrap_or(1);
+            params.suffix.clear();
+            params.sparse(&lengths)
+        }
+        _ => fail(USAGE),
+    };
+    let hash = hash.unwrap_or_else(|e| fail(&e));
+    if sparse {
+        for row in hash.state.chunks(16) {
+            let row: Vec<_> = row.iter().map(|n| format!("{:>3}", n)).collect();
+            println!("{}", row.join(" "));
+        }
+    }
+    if product {
+        println!("{}", hash.product());
+    }
+    println!("{}", hash.to_hex());
+}
+
+/// Reads commands until `q` or the end of input. Blank lines are skipped and
+/// everything else goes to `command`, trimmed.
+fn repl<F>(prompt: &str, mut command: F)
+where
+    F: FnMut(&str) -> Result<String, String>,
+{
+    let stdin = io::stdin();
+    loop {
+        print!("{}", prompt);
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        let result = match line.trim() {
+            "" => continue,
+            "q" | "quit" => break,
+            line => command(line),
+        };
+        match result {
+            Ok(out) => println!("{}", out),
+            Err(err) => println!("error: {}", err),
+        }
+    }
 }
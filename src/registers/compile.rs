@@ -0,0 +1,149 @@
+//! For running one program many times over: register names are interned to
+//! indices into a plain slice, and conditions are flattened so the hot loop
+//! never touches a `HashMap` or a `Box`.
+
+use std::collections::HashMap;
+
+use super::{Arg, Cmd, Cond, Error, Instr, Op};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Val {
+    Reg(usize),
+    Num(i64),
+}
+
+impl Val {
+    fn resolve(self, regs: &[i64]) -> i64 {
+        match self {
+            Val::Reg(r) => regs[r],
+            Val::Num(n) => n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Test {
+    pub op: Op,
+    pub a: Val,
+    pub b: Val,
+}
+
+/// One `Instr`. The condition is any one of `clauses` having all of its tests
+/// pass; no clauses at all means there was no `if`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Code {
+    pub reg: usize,
+    pub cmd: Cmd,
+    pub val: Val,
+    pub clauses: Vec<Vec<Test>>,
+    /// where it came from, for errors
+    pub line: usize,
+}
+
+impl Code {
+    fn fires(&self, regs: &[i64]) -> bool {
+        self.clauses.is_empty()
+            || self.clauses.iter().any(|clause| {
+                clause
+                    .iter()
+                    .all(|t| t.op.eval(t.a.resolve(regs), t.b.resolve(regs)))
+            })
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program<'a> {
+    /// every register the program mentions; `Val::Reg` indexes into this
+    pub names: Vec<&'a str>,
+    pub code: Vec<Code>,
+}
+
+impl<'a> Program<'a> {
+    /// Takes what `parse` gives back
+    pub fn compile(instrs: &[(usize, Instr<'a>)]) -> Program<'a> {
+        let mut names = Vec::new();
+        let mut index = HashMap::new();
+        let mut intern = |name: &'a str| {
+            *index.entry(name).or_insert_with(|| {
+                names.push(name);
+                names.len() - 1
+            })
+        };
+        let mut code = Vec::new();
+        for (line, instr) in instrs {
+            let mut val = |arg: &Arg<'a>| match *arg {
+                Arg::Reg(name) => Val::Reg(intern(name)),
+                Arg::Num(n) => Val::Num(n),
+            };
+            let mut clauses = Vec::new();
+            for clause in instr.cond.as_ref().map(dnf).unwrap_or_default() {
+                let test = |(op, a, b)| Test {
+                    op,
+                    a: val(&a),
+                    b: val(&b),
+                };
+                clauses.push(clause.into_iter().map(test).collect());
+            }
+            let val = val(&instr.val);
+            code.push(Code {
+                reg: intern(instr.reg),
+                cmd: instr.cmd,
+                val,
+                clauses,
+                line: *line,
+            });
+        }
+        Program { names, code }
+    }
+
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|&n| n == name)
+    }
+
+    /// A fresh set of registers, all 0
+    pub fn regs(&self) -> Vec<i64> {
+        vec![0; self.names.len()]
+    }
+
+    /// Runs once over `regs`, which has to come from `regs` (or be as long).
+    /// Returns the largest value any register held along the way, counting
+    /// the ones it started with.
+    pub fn run(&self, regs: &mut [i64]) -> Result<i64, Error> {
+        let mut highest = regs.iter().cloned().max().unwrap_or(0);
+        for code in &self.code {
+            if !code.fires(regs) {
+                continue;
+            }
+            let val = code.val.resolve(regs);
+            let reg = &mut regs[code.reg];
+            *reg = code.cmd.apply(*reg, val).ok_or_else(|| Error::Overflow {
+                line: code.line,
+                reg: self.names[code.reg].to_string(),
+            })?;
+            highest = highest.max(*reg);
+        }
+        Ok(highest)
+    }
+}
+
+/// The leaves of `cond` as an `or` of `and`s
+fn dnf<'a>(cond: &Cond<'a>) -> Vec<Vec<(Op, Arg<'a>, Arg<'a>)>> {
+    match cond {
+        Cond::Cmp(op, a, b) => vec![vec![(*op, *a, *b)]],
+        Cond::Or(a, b) => {
+            let mut clauses = dnf(a);
+            clauses.extend(dnf(b));
+            clauses
+        }
+        Cond::And(a, b) => {
+            let (a, b) = (dnf(a), dnf(b));
+            let mut clauses = Vec::new();
+            for x in &a {
+                for y in &b {
+                    clauses.push(x.iter().chain(y).cloned().collect());
+                }
+            }
+            clauses
+        }
+    }
+}
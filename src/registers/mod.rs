@@ -0,0 +1,446 @@
+//! The register language from day 8: each line changes one register, but
+//! only if its condition holds.
+//!
+//! ```txt
+//! b inc 5 if a > 1
+//! a set b
+//! c mul -2 if a >= 1 and b != 0 or c == 10
+//! ```
+//!
+//! `and` binds tighter than `or`, and the `if` is optional.
+
+pub mod compile;
+mod repl;
+
+use std::{collections::HashMap, fmt};
+
+pub use compile::Program;
+pub use repl::Session;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Error {
+    /// lines count from 1
+    Parse {
+        line: usize,
+        msg: String,
+    },
+    Overflow {
+        line: usize,
+        reg: String,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Parse { line, msg } => write!(f, "line {}: {}", line, msg),
+            Error::Overflow { line, reg } => write!(f, "line {}: {} overflowed", line, reg),
+        }
+    }
+}
+
+/// A register went past what an `i64` can hold
+#[derive(Debug, PartialEq, Eq)]
+pub struct Overflow<'a>(pub &'a str);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    Eq,
+    Neq,
+}
+
+impl Op {
+    pub fn eval(&self, a: i64, b: i64) -> bool {
+        match self {
+            Op::Lt => a < b,
+            Op::Lte => a <= b,
+            Op::Gt => a > b,
+            Op::Gte => a >= b,
+            Op::Eq => a == b,
+            Op::Neq => a != b,
+        }
+    }
+
+    pub fn parse(op: &str) -> Result<Op, String> {
+        match op {
+            ">" => Ok(Op::Gt),
+            ">=" => Ok(Op::Gte),
+            "<" => Ok(Op::Lt),
+            "<=" => Ok(Op::Lte),
+            "==" => Ok(Op::Eq),
+            "!=" => Ok(Op::Neq),
+            _ => Err(format!("unknown comparison: {}", op)),
+        }
+    }
+}
+
+impl fmt::Display for Op {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Op::Lt => "<",
+            Op::Lte => "<=",
+            Op::Gt => ">",
+            Op::Gte => ">=",
+            Op::Eq => "==",
+            Op::Neq => "!=",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Arg<'a> {
+    Num(i64),
+    Reg(&'a str),
+}
+
+impl<'a> Arg<'a> {
+    pub fn resolve(&self, regs: &Regs) -> i64 {
+        match self {
+            Arg::Num(n) => *n,
+            Arg::Reg(reg) => regs.get(reg),
+        }
+    }
+
+    /// register names are anything that starts with a letter
+    pub fn parse(arg: &'a str) -> Result<Arg<'a>, String> {
+        match arg.parse::<i64>() {
+            Ok(n) => Ok(Arg::Num(n)),
+            Err(_) if is_reg(arg) => Ok(Arg::Reg(arg)),
+            Err(_) => Err(format!("not a register or a number: {}", arg)),
+        }
+    }
+}
+
+impl fmt::Display for Arg<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Arg::Num(n) => write!(f, "{}", n),
+            Arg::Reg(reg) => write!(f, "{}", reg),
+        }
+    }
+}
+
+fn is_reg(token: &str) -> bool {
+    let mut chars = token.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !matches!(token, "if" | "and" | "or")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Cond<'a> {
+    Cmp(Op, Arg<'a>, Arg<'a>),
+    And(Box<Cond<'a>>, Box<Cond<'a>>),
+    Or(Box<Cond<'a>>, Box<Cond<'a>>),
+}
+
+impl<'a> Cond<'a> {
+    pub fn eval(&self, regs: &Regs) -> bool {
+        match self {
+            Cond::Cmp(op, a, b) => op.eval(a.resolve(regs), b.resolve(regs)),
+            Cond::And(a, b) => a.eval(regs) && b.eval(regs),
+            Cond::Or(a, b) => a.eval(regs) || b.eval(regs),
+        }
+    }
+
+    /// Like `Display`, but with whether each comparison held
+    pub fn explain(&self, regs: &Regs) -> String {
+        match self {
+            Cond::Cmp(..) => format!("{} ({})", self, self.eval(regs)),
+            Cond::And(a, b) => format!("{} and {}", a.explain(regs), b.explain(regs)),
+            Cond::Or(a, b) => format!("{} or {}", a.explain(regs), b.explain(regs)),
+        }
+    }
+
+    /// `tokens` is everything after the `if`
+    pub fn parse(tokens: &[&'a str]) -> Result<Cond<'a>, String> {
+        let mut any = None;
+        for clause in tokens.split(|&t| t == "or") {
+            let mut all = None;
+            for cmp in clause.split(|&t| t == "and") {
+                let cmp = match cmp {
+                    [a, op, b] => Cond::Cmp(Op::parse(op)?, Arg::parse(a)?, Arg::parse(b)?),
+                    _ => return Err(format!("expected `<a> <op> <b>`: {}", cmp.join(" "))),
+                };
+                all = Some(match all {
+                    Some(prev) => Cond::And(Box::new(prev), Box::new(cmp)),
+                    None => cmp,
+                });
+            }
+            let all = all.unwrap();
+            any = Some(match any {
+                Some(prev) => Cond::Or(Box::new(prev), Box::new(all)),
+                None => all,
+            });
+        }
+        Ok(any.unwrap())
+    }
+}
+
+impl fmt::Display for Cond<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Cond::Cmp(op, a, b) => write!(f, "{} {} {}", a, op, b),
+            Cond::And(a, b) => write!(f, "{} and {}", a, b),
+            Cond::Or(a, b) => write!(f, "{} or {}", a, b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cmd {
+    Inc,
+    Dec,
+    Set,
+    Mul,
+}
+
+impl Cmd {
+    pub fn parse(input: &str) -> Result<Cmd, String> {
+        match input {
+            "inc" => Ok(Cmd::Inc),
+            "dec" => Ok(Cmd::Dec),
+            "set" => Ok(Cmd::Set),
+            "mul" => Ok(Cmd::Mul),
+            _ => Err(format!("unknown command: {}", input)),
+        }
+    }
+
+    /// `None` if the result doesn't fit
+    pub fn apply(&self, current: i64, val: i64) -> Option<i64> {
+        match self {
+            Cmd::Inc => current.checked_add(val),
+            Cmd::Dec => current.checked_sub(val),
+            Cmd::Set => Some(val),
+            Cmd::Mul => current.checked_mul(val),
+        }
+    }
+}
+
+impl fmt::Display for Cmd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Cmd::Inc => "inc",
+            Cmd::Dec => "dec",
+            Cmd::Set => "set",
+            Cmd::Mul => "mul",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instr<'a> {
+    pub reg: &'a str,
+    pub cmd: Cmd,
+    pub val: Arg<'a>,
+    pub cond: Option<Cond<'a>>,
+}
+
+impl<'a> Instr<'a> {
+    pub fn parse(input: &'a str) -> Result<Instr<'a>, String> {
+        let tokens: Vec<_> = input.split_ascii_whitespace().collect();
+        let (reg, cmd, val) = match tokens[..] {
+            [reg, cmd, val, ..] => (reg, cmd, val),
+            _ => return Err(format!("expected `<reg> <cmd> <val>`: {}", input)),
+        };
+        if !is_reg(reg) {
+            return Err(format!("not a register: {}", reg));
+        }
+        let cond = match tokens[3..] {
+            [] => None,
+            ["if", ref rest @ ..] => Some(Cond::parse(rest)?),
+            _ => return Err(format!("expected `if` after the value: {}", input)),
+        };
+        Ok(Instr {
+            reg,
+            cmd: Cmd::parse(cmd)?,
+            val: Arg::parse(val)?,
+            cond,
+        })
+    }
+}
+
+impl fmt::Display for Instr<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} {}", self.reg, self.cmd, self.val)?;
+        if let Some(cond) = &self.cond {
+            write!(f, " if {}", cond)?;
+        }
+        Ok(())
+    }
+}
+
+/// Blank lines are skipped, so the line numbers in errors match the source
+pub fn parse(source: &str) -> Result<Vec<(usize, Instr<'_>)>, Error> {
+    source
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            let instr = Instr::parse(line).map_err(|msg| Error::Parse { line: i + 1, msg })?;
+            Ok((i + 1, instr))
+        })
+        .collect()
+}
+
+/// A value a register held, and which instruction put it there. `instr` is
+/// `None` for the 0 every register starts with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Extreme {
+    pub val: i64,
+    pub instr: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub max: Extreme,
+    pub min: Extreme,
+}
+
+impl Stats {
+    fn record(&mut self, val: i64, instr: usize) {
+        let now = Extreme {
+            val,
+            instr: Some(instr),
+        };
+        if val > self.max.val {
+            self.max = now;
+        }
+        if val < self.min.val {
+            self.min = now;
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Stats {
+        let start = Extreme {
+            val: 0,
+            instr: None,
+        };
+        Stats {
+            max: start,
+            min: start,
+        }
+    }
+}
+
+/// One call to `Regs::exec`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step<'a> {
+    pub reg: &'a str,
+    /// whether the condition held
+    pub fired: bool,
+    pub before: i64,
+    pub after: i64,
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Regs<'a> {
+    pub regs: HashMap<&'a str, i64>,
+    /// instructions executed so far, which is also the index of the next one
+    /// for a program run from the top
+    pub steps: usize,
+    stats: HashMap<&'a str, Stats>,
+    highest: Option<(&'a str, Extreme)>,
+    history: Option<Vec<Step<'a>>>,
+}
+
+impl<'a> Regs<'a> {
+    pub fn new() -> Regs<'a> {
+        Regs::default()
+    }
+
+    /// Also keeps every step, see `history`
+    pub fn with_history() -> Regs<'a> {
+        Regs {
+            history: Some(Vec::new()),
+            ..Regs::default()
+        }
+    }
+
+    pub fn get(&self, reg: &str) -> i64 {
+        self.regs.get(reg).cloned().unwrap_or_default()
+    }
+
+    /// returns whether the condition held
+    pub fn exec(&mut self, instr: &Instr<'a>) -> Result<bool, Overflow<'a>> {
+        let before = self.get(instr.reg);
+        let fired = instr.cond.as_ref().is_none_or(|cond| cond.eval(self));
+        let after = if fired {
+            let val = instr.val.resolve(self);
+            let after = instr.cmd.apply(before, val).ok_or(Overflow(instr.reg))?;
+            self.regs.insert(instr.reg, after);
+            let stats = self.stats.entry(instr.reg).or_default();
+            stats.record(after, self.steps);
+            if self.highest.is_none_or(|(_, top)| stats.max.val > top.val) {
+                self.highest = Some((instr.reg, stats.max));
+            }
+            after
+        } else {
+            before
+        };
+        if let Some(history) = &mut self.history {
+            history.push(Step {
+                reg: instr.reg,
+                fired,
+                before,
+                after,
+            });
+        }
+        self.steps += 1;
+        Ok(fired)
+    }
+
+    /// `None` for registers that have never been written
+    pub fn stats(&self, reg: &str) -> Option<Stats> {
+        self.stats.get(reg).cloned()
+    }
+
+    pub fn max(&self, reg: &str) -> Option<Extreme> {
+        self.stats(reg).map(|stats| stats.max)
+    }
+
+    pub fn min(&self, reg: &str) -> Option<Extreme> {
+        self.stats(reg).map(|stats| stats.min)
+    }
+
+    /// The largest value any register has ever held. Ties go to whichever got
+    /// there first.
+    pub fn highest(&self) -> Option<(&'a str, Extreme)> {
+        self.highest
+    }
+
+    /// Only kept for `Regs::with_history`
+    pub fn history(&self) -> Option<&[Step<'a>]> {
+        self.history.as_deref()
+    }
+}
+
+/// Every register that has been written, by name
+impl fmt::Display for Regs<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut regs: Vec<_> = self.regs.iter().collect();
+        regs.sort();
+        let regs: Vec<_> = regs.iter().map(|(r, v)| format!("{}={}", r, v)).collect();
+        write!(f, "{}", regs.join(" "))
+    }
+}
+
+/// Parses and runs a whole program, starting from empty registers
+pub fn run(source: &str) -> Result<Regs<'_>, Error> {
+    let mut regs = Regs::new();
+    for (line, instr) in parse(source)? {
+        regs.exec(&instr).map_err(|Overflow(reg)| Error::Overflow {
+            line,
+            reg: reg.to_string(),
+        })?;
+    }
+    Ok(regs)
+}
@@ -0,0 +1,128 @@
+use std::fs;
+
+use super::{parse, Instr, Overflow, Regs};
+
+/// An interactive session: instructions typed one at a time, plus a few
+/// `:commands`. The registers borrow their names from the instructions, so
+/// the session keeps its own copy of every line it has run and runs them
+/// again whenever it needs the registers.
+#[derive(Debug, Default)]
+pub struct Session {
+    /// every instruction run so far, loaded ones included, oldest first
+    lines: Vec<String>,
+    /// how many lines there were before each change, most recent last
+    undo: Vec<usize>,
+}
+
+impl Session {
+    pub fn new() -> Session {
+        Session::default()
+    }
+
+    /// Where things stand after every line so far
+    pub fn regs(&self) -> Regs<'_> {
+        let mut regs = Regs::new();
+        for line in &self.lines {
+            let instr = Instr::parse(line).expect("only lines that parsed are kept");
+            regs.exec(&instr)
+                .expect("only lines that didn't overflow are kept");
+        }
+        regs
+    }
+
+    /// Runs a whole program on top of the current registers. It's undone in
+    /// one go, and leaves the registers alone if it fails.
+    pub fn load(&mut self, source: &str) -> Result<String, String> {
+        let program = parse(source).map_err(|e| e.to_string())?;
+        let mut regs = self.regs();
+        for (line, instr) in &program {
+            if let Err(Overflow(reg)) = regs.exec(instr) {
+                return Err(format!("line {}: {} overflowed", line, reg));
+            }
+        }
+        let out = format!("ran {} instructions\n{}", program.len(), regs);
+        self.undo.push(self.lines.len());
+        let lines = source.lines().filter(|line| !line.trim().is_empty());
+        self.lines.extend(lines.map(str::to_string));
+        Ok(out)
+    }
+
+    /// `load` with the contents of a file
+    pub fn load_file(&mut self, path: &str) -> Result<String, String> {
+        let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        self.load(&source)
+    }
+
+    pub fn command(&mut self, line: &str) -> Result<String, String> {
+        let line = line.trim();
+        let (cmd, rest) = line.split_once(' ').unwrap_or((line, ""));
+        match cmd {
+            ":load" => match rest.trim() {
+                "" => Err("expected a file".to_string()),
+                path => self.load_file(path),
+            },
+            ":regs" => Ok(self.regs().to_string()),
+            ":max" => Ok(max(&self.regs())),
+            ":undo" => {
+                let len = self.undo.pop().ok_or("nothing to undo")?;
+                self.lines.truncate(len);
+                Ok(self.regs().to_string())
+            }
+            ":help" => Ok(HELP.to_string()),
+            cmd if cmd.starts_with(':') => Err(format!("unknown command: {}", cmd)),
+            _ => self.exec(line),
+        }
+    }
+
+    fn exec(&mut self, line: &str) -> Result<String, String> {
+        let instr = Instr::parse(line)?;
+        let mut regs = self.regs();
+        let mut out = match &instr.cond {
+            Some(cond) => format!("if {}\n", cond.explain(&regs)),
+            None => String::new(),
+        };
+        let was = regs.get(instr.reg);
+        let fired = regs
+            .exec(&instr)
+            .map_err(|Overflow(reg)| format!("{} would overflow", reg))?;
+        if fired {
+            out += &format!("{} = {} (was {})\n", instr.reg, regs.get(instr.reg), was);
+        } else {
+            out += "skipped\n";
+        }
+        out += &regs.to_string();
+        if fired {
+            self.undo.push(self.lines.len());
+        }
+        self.lines.push(line.to_string());
+        Ok(out.trim_end().to_string())
+    }
+}
+
+fn max(regs: &Regs) -> String {
+    let now = regs
+        .regs
+        .iter()
+        .max_by_key(|&(r, v)| (v, std::cmp::Reverse(r)));
+    let now = match now {
+        Some((reg, val)) => format!("largest now: {}={}", reg, val),
+        None => return "no registers yet".to_string(),
+    };
+    match regs.highest() {
+        Some((reg, top)) => match top.instr {
+            Some(i) => format!("{}\nlargest ever: {}={} (step {})", now, reg, top.val, i),
+            None => format!(
+                "{}\nlargest ever: {}={} (from the start)",
+                now, reg, top.val
+            ),
+        },
+        None => now,
+    }
+}
+
+const HELP: &str = "\
+<reg> <inc|dec|set|mul> <val> [if <cond>]   run one instruction
+:regs                                       show every register
+:max                                        the largest values, now and ever
+:undo                                       take back the last change
+:load <file>                                run a whole file";
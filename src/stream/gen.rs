@@ -0,0 +1,107 @@
+//! Random streams with known answers, for testing the parsers against.
+
+use super::Totals;
+
+/// xorshift64*, which is plenty for making up test input
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    /// Any seed works, 0 included. The one seed that would leave the state at
+    /// 0, where it would stay, gets the same stream as 0.
+    pub fn new(seed: u64) -> Rng {
+        const MIX: u64 = 0x9e37_79b9_7f4a_7c15;
+        match seed ^ MIX {
+            0 => Rng(MIX),
+            state => Rng(state),
+        }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    /// in `0..n`
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+
+    pub fn pick<T: Copy>(&mut self, items: &[T]) -> T {
+        items[self.below(items.len())]
+    }
+}
+
+/// What can turn up inside garbage. The brackets and commas are there to look
+/// like groups when they aren't.
+const INSIDE: &[u8] = b"ab{}<,'\"";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shape {
+    /// groups go no deeper than this, the outermost one being 1
+    pub max_depth: usize,
+    /// most elements directly inside one group
+    pub max_elements: usize,
+    /// longest hunk of garbage, not counting the `<>` or any `!`s
+    pub max_garbage: usize,
+}
+
+impl Default for Shape {
+    fn default() -> Shape {
+        Shape {
+            max_depth: 6,
+            max_elements: 4,
+            max_garbage: 6,
+        }
+    }
+}
+
+/// A valid stream, and what `scan` should make of it
+pub fn stream(rng: &mut Rng, shape: Shape) -> (String, Totals) {
+    let mut out = String::new();
+    let mut totals = Totals::default();
+    group(rng, shape, 1, &mut out, &mut totals);
+    totals.len = out.len();
+    (out, totals)
+}
+
+fn group(rng: &mut Rng, shape: Shape, depth: usize, out: &mut String, totals: &mut Totals) {
+    totals.groups += 1;
+    totals.score += depth as u64;
+    totals.max_depth = totals.max_depth.max(depth);
+    out.push('{');
+    for i in 0..rng.below(shape.max_elements + 1) {
+        if i > 0 {
+            out.push(',');
+        }
+        if depth < shape.max_depth && rng.below(2) == 0 {
+            group(rng, shape, depth + 1, out, totals);
+        } else {
+            garbage(rng, shape, out, totals);
+        }
+    }
+    out.push('}');
+}
+
+fn garbage(rng: &mut Rng, shape: Shape, out: &mut String, totals: &mut Totals) {
+    out.push('<');
+    for _ in 0..rng.below(shape.max_garbage + 1) {
+        if rng.below(4) == 0 {
+            // cancel something, `>` and `!` included
+            out.push('!');
+            out.push(rng.pick(b">!ab<") as char);
+        } else {
+            out.push(rng.pick(INSIDE) as char);
+            totals.garbage += 1;
+        }
+    }
+    out.push('>');
+}
+
+/// Bytes that are mostly the ones the parser cares about, for fuzzing
+pub fn noise(rng: &mut Rng, len: usize) -> Vec<u8> {
+    const BYTES: &[u8] = b"{{{}}}<<>>!!,,a \n\xff";
+    (0..len).map(|_| rng.pick(BYTES)).collect()
+}
@@ -0,0 +1,203 @@
+//! The character stream from day 9: groups in `{}` separated by commas, with
+//! garbage in `<>` that can hold anything, and `!` cancelling whatever comes
+//! after it.
+//!
+//! ```txt
+//! {{<ab>},{<!>},{<!!>}}
+//! ```
+
+pub mod gen;
+pub mod tree;
+
+use std::{
+    fmt,
+    io::{self, Read},
+};
+
+pub use tree::{Element, Garbage, Group, Stats};
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    Syntax(SyntaxError),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Syntax(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+/// Where a stream stopped making sense
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    /// in bytes, from 0
+    pub offset: usize,
+    /// from 1, like an editor would
+    pub line: usize,
+    /// in bytes, from 1
+    pub col: usize,
+    /// which group it happened in: the index of each group among its
+    /// parent's groups, outermost first, so the outermost group is `[0]`.
+    /// Empty outside of it.
+    pub path: Vec<usize>,
+    pub expected: &'static str,
+    /// the byte that didn't fit, or `None` for the end of the stream
+    pub found: Option<u8>,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}, column {}", self.line, self.col)?;
+        if !self.path.is_empty() {
+            let path: Vec<_> = self.path.iter().map(usize::to_string).collect();
+            write!(f, ", in group {}", path.join("."))?;
+        }
+        write!(f, ": expected {}, found ", self.expected)?;
+        match self.found {
+            Some(byte) if byte.is_ascii() => write!(f, "{:?}", byte as char),
+            // most likely one byte of a longer UTF-8 character
+            Some(byte) => write!(f, "{:#04x}", byte),
+            None => write!(f, "the end of the stream"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Totals {
+    /// every group's depth, added up
+    pub score: u64,
+    pub groups: u64,
+    /// bytes inside garbage, not counting `!` or what it cancels
+    pub garbage: u64,
+    pub max_depth: usize,
+    /// bytes up to the end of the outermost group, so not counting any
+    /// whitespace after it
+    pub len: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// before the outermost group
+    Start,
+    /// just inside a `{`
+    Open,
+    /// just past an element
+    Element,
+    Comma,
+    Garbage,
+    /// just past a `!`
+    Cancel,
+    /// past the outermost group, where only whitespace is allowed
+    Done,
+}
+
+impl State {
+    /// what would have been fine here
+    fn expected(self) -> &'static str {
+        match self {
+            State::Start => "'{'",
+            State::Open => "'{', '<' or '}'",
+            State::Element => "',' or '}'",
+            State::Comma => "'{' or '<'",
+            State::Garbage => "'>'",
+            State::Cancel => "a character to cancel",
+            State::Done => "the end of the stream",
+        }
+    }
+}
+
+/// One open group
+#[derive(Debug, Clone, Copy)]
+struct Level {
+    /// among its parent's groups
+    index: usize,
+    /// groups seen inside it so far
+    groups: usize,
+}
+
+/// Reads the whole stream in one pass. Nesting costs a couple of counters per
+/// level, so there's no limit on how deep the groups can go.
+pub fn scan<R: Read>(mut reader: R) -> Result<Totals, Error> {
+    let mut totals = Totals::default();
+    let mut state = State::Start;
+    let mut levels: Vec<Level> = Vec::new();
+    let (mut offset, mut line, mut col) = (0, 1, 1);
+    let mut buf = [0; 8192];
+    let err = |state: State, levels: &[Level], pos: (usize, usize, usize), found| {
+        Error::Syntax(SyntaxError {
+            offset: pos.0,
+            line: pos.1,
+            col: pos.2,
+            path: levels.iter().map(|level| level.index).collect(),
+            expected: state.expected(),
+            found,
+        })
+    };
+    loop {
+        let n = match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(err) if err.kind() == io::ErrorKind::Interrupted => continue,
+            Err(err) => return Err(err.into()),
+        };
+        for &byte in &buf[..n] {
+            state = match (state, byte) {
+                (State::Garbage, b'!') => State::Cancel,
+                (State::Garbage, b'>') => State::Element,
+                (State::Garbage, _) => {
+                    totals.garbage += 1;
+                    State::Garbage
+                }
+                (State::Cancel, _) => State::Garbage,
+                (State::Start, b'{') | (State::Open, b'{') | (State::Comma, b'{') => {
+                    let index = match levels.last_mut() {
+                        Some(parent) => {
+                            parent.groups += 1;
+                            parent.groups - 1
+                        }
+                        None => 0,
+                    };
+                    levels.push(Level { index, groups: 0 });
+                    totals.groups += 1;
+                    totals.score += levels.len() as u64;
+                    totals.max_depth = totals.max_depth.max(levels.len());
+                    State::Open
+                }
+                (State::Open, b'<') | (State::Comma, b'<') => State::Garbage,
+                (State::Open, b'}') | (State::Element, b'}') => {
+                    levels.pop();
+                    if levels.is_empty() {
+                        totals.len = offset + 1;
+                        State::Done
+                    } else {
+                        State::Element
+                    }
+                }
+                (State::Element, b',') => State::Comma,
+                (State::Done, byte) if byte.is_ascii_whitespace() => State::Done,
+                _ => return Err(err(state, &levels, (offset, line, col), Some(byte))),
+            };
+            offset += 1;
+            if byte == b'\n' {
+                line += 1;
+                col = 1;
+            } else {
+                col += 1;
+            }
+        }
+    }
+    match state {
+        State::Done => Ok(totals),
+        _ => Err(err(state, &levels, (offset, line, col), None)),
+    }
+}
@@ -0,0 +1,230 @@
+//! The whole stream held in memory as a tree, for when the totals from
+//! `scan` aren't enough. Everything borrows from the input, and each group's
+//! children are reached by their index among the groups (not the garbage) of
+//! its parent.
+
+use std::{borrow::Cow, fmt, mem};
+
+use super::{scan, Error, SyntaxError};
+
+/// One hunk of garbage, exactly as written, `<` and `>` included
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Garbage<'a>(pub &'a [u8]);
+
+impl<'a> Garbage<'a> {
+    pub fn size_chars(&self) -> usize {
+        self.0.len()
+    }
+
+    /// bytes inside, not counting `!` or what it cancels
+    pub fn valid_chars(&self) -> usize {
+        let mut count = 0;
+        let mut escaped = false;
+        for &c in &self.0[1..self.0.len() - 1] {
+            if escaped {
+                escaped = false;
+            } else if c == b'!' {
+                escaped = true;
+            } else {
+                count += 1;
+            }
+        }
+        count
+    }
+
+    pub fn text(&self) -> Cow<'a, str> {
+        String::from_utf8_lossy(self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Element<'a> {
+    Group(Group<'a>),
+    Garbage(Garbage<'a>),
+}
+
+impl Element<'_> {
+    pub fn size_chars(&self) -> usize {
+        match self {
+            Element::Group(g) => g.size_chars(),
+            Element::Garbage(g) => g.size_chars(),
+        }
+    }
+}
+
+/// What's in one group, not counting what's in the groups inside it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// the outermost group is at 1
+    pub depth: usize,
+    pub groups: usize,
+    pub hunks: usize,
+    /// as counted by `Garbage::valid_chars`
+    pub garbage: usize,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group<'a>(pub Vec<Element<'a>>);
+
+impl<'a> Group<'a> {
+    /// The outermost group, which can be followed by whitespace but nothing
+    /// else. Deep nesting is fine for parsing and dropping, but everything
+    /// else here recurses, derived traits included; `scan` has no such limit.
+    pub fn parse(input: &'a [u8]) -> Result<Group<'a>, SyntaxError> {
+        match scan(input) {
+            Ok(_) => (),
+            Err(Error::Syntax(err)) => return Err(err),
+            Err(Error::Io(_)) => unreachable!("reading from a slice can't fail"),
+        }
+        // it's valid, so all that's left is to find where things start and end
+        let mut open: Vec<Vec<Element>> = Vec::new();
+        let mut i = 0;
+        loop {
+            match input[i] {
+                b'{' => open.push(Vec::new()),
+                b'}' => {
+                    let group = Group(open.pop().unwrap());
+                    match open.last_mut() {
+                        Some(parent) => parent.push(Element::Group(group)),
+                        None => return Ok(group),
+                    }
+                }
+                b'<' => {
+                    let start = i;
+                    while input[i] != b'>' {
+                        i += if input[i] == b'!' { 2 } else { 1 };
+                    }
+                    let garbage = Garbage(&input[start..=i]);
+                    open.last_mut().unwrap().push(Element::Garbage(garbage));
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+
+    pub fn size_chars(&self) -> usize {
+        let commas = self.0.len().saturating_sub(1);
+        self.0.iter().map(Element::size_chars).sum::<usize>() + commas + 2
+    }
+
+    /// `depth` is this group's own score
+    pub fn score(&self, depth: u32) -> u32 {
+        depth + self.groups().map(|g| g.score(depth + 1)).sum::<u32>()
+    }
+
+    pub fn count_garbage_chars(&self) -> usize {
+        let own: usize = self.hunks().map(Garbage::valid_chars).sum();
+        own + self.groups().map(Group::count_garbage_chars).sum::<usize>()
+    }
+
+    /// The groups directly inside this one
+    pub fn groups(&self) -> impl Iterator<Item = &Group<'a>> {
+        self.0.iter().filter_map(|e| match e {
+            Element::Group(g) => Some(g),
+            Element::Garbage(_) => None,
+        })
+    }
+
+    /// The garbage directly inside this one
+    pub fn hunks(&self) -> impl Iterator<Item = &Garbage<'a>> {
+        self.0.iter().filter_map(|e| match e {
+            Element::Garbage(g) => Some(g),
+            Element::Group(_) => None,
+        })
+    }
+
+    /// Follows `path` down from here; an empty one is this group. The paths in
+    /// a `SyntaxError` are the same, but start from outside the outermost
+    /// group, so they have an extra 0 in front.
+    pub fn get(&self, path: &[usize]) -> Option<&Group<'a>> {
+        path.iter()
+            .try_fold(self, |group, &i| group.groups().nth(i))
+    }
+
+    /// `stats` for the group at `path`, taking this one to be the outermost
+    pub fn stats(&self, path: &[usize]) -> Option<Stats> {
+        let group = self.get(path)?;
+        Some(Stats {
+            depth: path.len() + 1,
+            groups: group.groups().count(),
+            hunks: group.hunks().count(),
+            garbage: group.hunks().map(Garbage::valid_chars).sum(),
+        })
+    }
+
+    /// Every group's path, outermost first and then in the order they're
+    /// written
+    pub fn paths(&self) -> Vec<Vec<usize>> {
+        let mut paths = vec![Vec::new()];
+        for (i, group) in self.groups().enumerate() {
+            for mut path in group.paths() {
+                path.insert(0, i);
+                paths.push(path);
+            }
+        }
+        paths
+    }
+
+    /// The same structure with the garbage gone, like `{{},{}}`
+    pub fn canonical(&self) -> String {
+        let groups: Vec<_> = self.groups().map(Group::canonical).collect();
+        format!("{{{}}}", groups.join(","))
+    }
+
+    /// One element per line, indented by depth. Groups with nothing in them
+    /// stay on one line.
+    pub fn pretty(&self) -> String {
+        let mut out = String::new();
+        self.pretty_into(&mut out, 0);
+        out
+    }
+
+    fn pretty_into(&self, out: &mut String, indent: usize) {
+        if self.0.is_empty() {
+            out.push_str("{}");
+            return;
+        }
+        out.push_str("{\n");
+        for (i, element) in self.0.iter().enumerate() {
+            out.push_str(&"    ".repeat(indent + 1));
+            match element {
+                Element::Group(g) => g.pretty_into(out, indent + 1),
+                Element::Garbage(g) => out.push_str(&g.text()),
+            }
+            out.push_str(if i + 1 < self.0.len() { ",\n" } else { "\n" });
+        }
+        out.push_str(&"    ".repeat(indent));
+        out.push('}');
+    }
+}
+
+/// Takes the tree apart a level at a time, rather than letting each group drop
+/// the ones inside it, which would recurse as deep as the nesting goes
+impl Drop for Group<'_> {
+    fn drop(&mut self) {
+        let mut elements = mem::take(&mut self.0);
+        while let Some(element) = elements.pop() {
+            if let Element::Group(mut group) = element {
+                elements.append(&mut group.0);
+            }
+        }
+    }
+}
+
+/// Exactly the text it was parsed from, as long as that was UTF-8
+impl fmt::Display for Group<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{")?;
+        for (i, element) in self.0.iter().enumerate() {
+            if i > 0 {
+                write!(f, ",")?;
+            }
+            match element {
+                Element::Group(g) => write!(f, "{}", g)?,
+                Element::Garbage(g) => write!(f, "{}", g.text())?,
+            }
+        }
+        write!(f, "}}")
+    }
+}
@@ -1,171 +1,270 @@
-use derive_new::new;
-use std::collections::HashMap;
-
-#[derive(Debug)]
-enum Op {
-    Lt,
-    Lte,
-    Gt,
-    Gte,
-    Eq,
-    Neq,
-}
+use std::time::Instant;
 
-impl Op {
-    fn eval<'a>(&self, a: i64, b: i64) -> bool {
-        match self {
-            Op::Lt => a < b,
-            Op::Lte => a <= b,
-            Op::Gt => a > b,
-            Op::Gte => a >= b,
-            Op::Eq => a == b,
-            Op::Neq => a != b,
-        }
-    }
+use aoc_17::registers::{
+    self, Arg, Cmd, Cond, Error, Extreme, Instr, Op, Program, Regs, Session, Step,
+};
 
-    fn parse(op: &str) -> Op {
-        match op {
-            ">" => Op::Gt,
-            ">=" => Op::Gte,
-            "<" => Op::Lt,
-            "<=" => Op::Lte,
-            "==" => Op::Eq,
-            "!=" => Op::Neq,
-            _ => panic!(),
-        }
-    }
+#[test]
+fn part1() {
+    let regs = registers::run(include_str!("res/08.txt")).unwrap();
+    let ans = regs.regs.values().max().unwrap();
+    println!("Day 8, part 1: {}", ans);
+    assert_eq!(4066, *ans);
 }
 
-#[derive(Debug, new)]
-enum Arg<'a> {
-    Num(i64),
-    Reg(&'a str),
+#[test]
+fn part2() {
+    let regs = registers::run(include_str!("res/08.txt")).unwrap();
+    let (_, ans) = regs.highest().unwrap();
+    println!("Day 8, part 2: {}", ans.val);
+    assert_eq!(4829, ans.val);
 }
 
-impl Arg<'_> {
-    fn resolve(&self, regs: &Regs) -> i64 {
-        match self {
-            Arg::Num(n) => *n,
-            Arg::Reg(reg) => regs.get(reg),
-        }
-    }
+#[test]
+fn test_parse() {
+    let instr = Instr::parse("c mul -2 if a >= 1 and b != x or c == 10").unwrap();
+    let cmp = |op, a, b| Box::new(Cond::Cmp(op, a, b));
+    let cond = Cond::Or(
+        Box::new(Cond::And(
+            cmp(Op::Gte, Arg::Reg("a"), Arg::Num(1)),
+            cmp(Op::Neq, Arg::Reg("b"), Arg::Reg("x")),
+        )),
+        cmp(Op::Eq, Arg::Reg("c"), Arg::Num(10)),
+    );
+    assert_eq!(instr.cmd, Cmd::Mul);
+    assert_eq!(instr.cond, Some(cond));
+    assert_eq!(
+        instr.to_string(),
+        "c mul -2 if a >= 1 and b != x or c == 10"
+    );
+    assert_eq!(Instr::parse("a set b").unwrap().cond, None);
 
-    fn parse(arg: &str) -> Arg {
-        match arg.parse::<i64>() {
-            Ok(n) => Arg::Num(n),
-            Err(_) => Arg::Reg(arg),
-        }
+    let errors = [
+        ("a inc", "expected `<reg> <cmd> <val>`: a inc"),
+        ("a add 1", "unknown command: add"),
+        ("1 inc 1", "not a register: 1"),
+        (
+            "a inc 1 when b > 0",
+            "expected `if` after the value: a inc 1 when b > 0",
+        ),
+        ("a inc 1 if b <> 0", "unknown comparison: <>"),
+        ("a inc 1 if b > 0 and", "expected `<a> <op> <b>`: "),
+        ("a inc 1 if b > 0 or c", "expected `<a> <op> <b>`: c"),
+        ("a inc 1 if b > $", "not a register or a number: $"),
+    ];
+    for (input, msg) in errors.iter() {
+        assert_eq!(Instr::parse(input), Err(msg.to_string()), "{}", input);
     }
+    let source = "a inc 1\n\nb dec 1 if a > 0\nc inc 1 iff a > 0";
+    let err = Error::Parse {
+        line: 4,
+        msg: "expected `if` after the value: c inc 1 iff a > 0".to_string(),
+    };
+    assert_eq!(registers::parse(source).unwrap_err(), err);
 }
 
-#[derive(Debug, new)]
-struct Cond<'a> {
-    op: Op,
-    a: Arg<'a>,
-    b: Arg<'a>,
-}
+#[test]
+fn test_run() {
+    let source = "\
+a set 3
+b set a
+b mul 4 if a == 3 and b == 3
+c inc 1 if a > 5 or b == 12
+d dec 1 if a > 5 and b == 12
+a mul b if c != 0";
+    let regs = registers::run(source).unwrap();
+    let vals: Vec<_> = ["a", "b", "c", "d", "e"]
+        .iter()
+        .map(|r| regs.get(r))
+        .collect();
+    assert_eq!(vals, [36, 12, 1, 0, 0]);
 
-impl<'a> Cond<'a> {
-    fn eval(&self, regs: &Regs) -> bool {
-        self.op.eval(self.a.resolve(regs), self.b.resolve(regs))
-    }
+    let source = "a set 9223372036854775807\nb inc 1\na inc b";
+    let err = Error::Overflow {
+        line: 3,
+        reg: "a".to_string(),
+    };
+    assert_eq!(registers::run(source).unwrap_err(), err);
+    assert_eq!(err.to_string(), "line 3: a overflowed");
+}
 
-    fn parse(a: &'a str, op: &'a str, b: &'a str) -> Cond<'a> {
-        Cond::new(Op::parse(op), Arg::parse(a), Arg::parse(b))
+#[test]
+fn test_stats() {
+    let source = "\
+a inc 5
+b dec 2 if a > 0
+a dec 9
+a inc 20 if b > 0
+b set 7 if a < 0
+a inc 4";
+    let program = registers::parse(source).unwrap();
+    let mut regs = Regs::with_history();
+    for (_, instr) in &program {
+        regs.exec(instr).unwrap();
     }
-}
+    let at = |val, instr| Extreme { val, instr };
+    assert_eq!(regs.max("a"), Some(at(5, Some(0))));
+    assert_eq!(regs.min("a"), Some(at(-4, Some(2))));
+    assert_eq!(regs.max("b"), Some(at(7, Some(4))));
+    assert_eq!(regs.min("b"), Some(at(-2, Some(1))));
+    assert_eq!(regs.max("c"), None);
+    assert_eq!(regs.highest(), Some(("b", at(7, Some(4)))));
+    assert_eq!(regs.steps, 6);
 
-#[derive(Debug)]
-enum Cmd {
-    Inc,
-    Dec,
-}
+    let history = regs.history().unwrap();
+    assert_eq!(history.len(), 6);
+    let step = |reg, fired, before, after| Step {
+        reg,
+        fired,
+        before,
+        after,
+    };
+    assert_eq!(history[3], step("a", false, -4, -4));
+    assert_eq!(history[5], step("a", true, -4, 0));
+    assert_eq!(Regs::new().history(), None);
 
-impl Cmd {
-    fn parse(input: &str) -> Cmd {
-        match input {
-            "inc" => Cmd::Inc,
-            "dec" => Cmd::Dec,
-            _ => panic!(),
-        }
+    // the part 2 answer by brute force
+    let program = registers::parse(include_str!("res/08.txt")).unwrap();
+    let mut regs = Regs::new();
+    let mut abs_max = 0;
+    for (_, instr) in &program {
+        regs.exec(instr).unwrap();
+        abs_max = abs_max.max(*regs.regs.values().max().unwrap_or(&0));
     }
+    assert_eq!(regs.highest().unwrap().1.val, abs_max);
 }
 
-#[derive(Debug, new)]
-struct Instr<'a> {
-    reg: &'a str,
-    cmd: Cmd,
-    val: i64,
-    cond: Cond<'a>,
-}
+#[test]
+fn test_session() {
+    let mut session = Session::new();
+    assert_eq!(
+        session.command("b inc 5 if a > 1"),
+        Ok("if a > 1 (false)\nskipped".to_string())
+    );
+    assert_eq!(
+        session.command("a inc 2"),
+        Ok("a = 2 (was 0)\na=2".to_string())
+    );
+    let out = session
+        .command("b inc 5 if a > 1 and b == 0 or a < 0")
+        .unwrap();
+    assert_eq!(
+        out,
+        "if a > 1 (true) and b == 0 (true) or a < 0 (false)\nb = 5 (was 0)\na=2 b=5"
+    );
+    assert_eq!(
+        session.command(":max").unwrap(),
+        "largest now: b=5\nlargest ever: b=5 (step 2)"
+    );
 
-impl Instr<'_> {
-    fn to_add(&self) -> i64 {
-        match self.cmd {
-            Cmd::Inc => self.val,
-            Cmd::Dec => -self.val,
-        }
-    }
+    let out = session.load("c dec 3\nb mul 2\n").unwrap();
+    assert_eq!(out, "ran 2 instructions\na=2 b=10 c=-3");
+    assert!(session.load("a inc 1\nb mul 9223372036854775807").is_err());
+    assert_eq!(session.command(":regs").unwrap(), "a=2 b=10 c=-3");
+    assert_eq!(session.command(":undo").unwrap(), "a=2 b=5");
+    assert_eq!(session.command(":undo").unwrap(), "a=2");
+    assert_eq!(session.command(":undo").unwrap(), "");
+    assert_eq!(session.command(":undo"), Err("nothing to undo".to_string()));
+    assert_eq!(
+        session.command(":redo"),
+        Err("unknown command: :redo".to_string())
+    );
+    assert_eq!(
+        session.command("a inc"),
+        Err("expected `<reg> <cmd> <val>`: a inc".to_string())
+    );
+    // the session keeps its own copy, so the line can go
+    let line = String::from("d set 7");
+    session.command(&line).unwrap();
+    drop(line);
+    assert_eq!(session.command(":regs").unwrap(), "d=7");
 
-    fn parse(input: &str) -> Instr<'_> {
-        let tokens: Vec<_> = input.split_ascii_whitespace().collect();
-        let reg = tokens[0];
-        let cmd = Cmd::parse(tokens[1]);
-        let val = tokens[2].parse().unwrap();
-        let cond = Cond::parse(tokens[4], tokens[5], tokens[6]);
-        Instr::new(reg, cmd, val, cond)
-    }
+    let mut session = Session::new();
+    assert_eq!(
+        session.command(":load tests/res/08ex.txt"),
+        Ok("ran 4 instructions\na=1 c=-10".to_string())
+    );
+    assert_eq!(session.command(":load"), Err("expected a file".to_string()));
+    assert!(session.command(":load tests/res/nowhere.txt").is_err());
 }
 
-#[derive(Debug)]
-struct Regs<'a> {
-    regs: HashMap<&'a str, i64>,
-}
+#[test]
+fn test_compile() {
+    let program = registers::parse(include_str!("res/08.txt")).unwrap();
+    let compiled = Program::compile(&program);
+    let mut vals = compiled.regs();
+    let highest = compiled.run(&mut vals).unwrap();
+    assert_eq!(vals.iter().max(), Some(&4066));
+    assert_eq!(highest, 4829);
 
-impl<'a> Regs<'a> {
-    fn new() -> Regs<'a> {
-        Regs {
-            regs: HashMap::new(),
+    // the same answers as the interpreter from any starting point
+    for seed in 1..20i64 {
+        let start = |i: usize| (seed * 7919 + i as i64 * 104729) % 2001 - 1000;
+        let mut regs = Regs::new();
+        for (i, name) in compiled.names.iter().enumerate() {
+            regs.regs.insert(name, start(i));
+        }
+        let mut vals: Vec<_> = (0..compiled.names.len()).map(start).collect();
+        let mut highest = *vals.iter().max().unwrap();
+        for (_, instr) in &program {
+            regs.exec(instr).unwrap();
+            highest = highest.max(*regs.regs.values().max().unwrap());
+        }
+        assert_eq!(compiled.run(&mut vals), Ok(highest));
+        for (i, name) in compiled.names.iter().enumerate() {
+            assert_eq!(vals[i], regs.get(name), "{}", name);
         }
     }
 
-    fn get(&self, reg: &str) -> i64 {
-        self.regs.get(reg).cloned().unwrap_or_default()
-    }
-
-    fn exec(&mut self, instr: &Instr<'a>) {
-        if instr.cond.eval(self) {
-            *self.regs.entry(instr.reg).or_insert(0) += instr.to_add();
+    // an `and` of `or`s gets multiplied out
+    let mut instr = Instr::parse("x inc 1").unwrap();
+    let cmp = |op, a| Box::new(Cond::Cmp(op, Arg::Reg(a), Arg::Num(0)));
+    instr.cond = Some(Cond::And(
+        Box::new(Cond::Or(cmp(Op::Gt, "a"), cmp(Op::Gt, "b"))),
+        Box::new(Cond::Or(cmp(Op::Gt, "c"), cmp(Op::Gt, "d"))),
+    ));
+    let compiled = Program::compile(&[(1, instr.clone())]);
+    assert_eq!(compiled.code[0].clauses.len(), 4);
+    for bits in 0..16 {
+        let mut vals = compiled.regs();
+        let mut regs = Regs::new();
+        for (i, name) in ["a", "b", "c", "d"].iter().enumerate() {
+            let val = (bits >> i) & 1;
+            vals[compiled.index(name).unwrap()] = val;
+            regs.regs.insert(name, val);
         }
+        compiled.run(&mut vals).unwrap();
+        regs.exec(&instr).unwrap();
+        assert_eq!(vals[compiled.index("x").unwrap()], regs.get("x"));
     }
-}
 
-#[test]
-fn part1() {
-    let raw = include_str!("res/08.txt").lines();
-    let program: Vec<Instr> = raw.map(Instr::parse).collect();
-    let mut regs = Regs::new();
-    for instr in &program {
-        regs.exec(instr);
-    }
-    let ans = regs.regs.values().max().unwrap();
-    println!("Day 8, part 1: {}", ans);
-    assert_eq!(4066, *ans);
+    let source = "a set 9223372036854775807\nb inc 1\na inc b";
+    let compiled = Program::compile(&registers::parse(source).unwrap());
+    let err = Error::Overflow {
+        line: 3,
+        reg: "a".to_string(),
+    };
+    assert_eq!(compiled.run(&mut compiled.regs()), Err(err));
 }
 
+/// `cargo test --release --test day08 bench_compile -- --ignored --nocapture`
 #[test]
-fn part2() {
-    let raw = include_str!("res/08.txt").lines();
-    let program: Vec<Instr> = raw.map(Instr::parse).collect();
-    let mut regs = Regs::new();
-    let mut abs_max = 0;
-    for instr in &program {
-        regs.exec(instr);
-        let cur_max = *regs.regs.values().max().unwrap_or(&0);
-        if cur_max > abs_max {
-            abs_max = cur_max;
+#[ignore]
+fn bench_compile() {
+    let program = registers::parse(include_str!("res/08.txt")).unwrap();
+    let start = Instant::now();
+    for _ in 0..1000 {
+        let mut regs = Regs::new();
+        for (_, instr) in &program {
+            regs.exec(instr).unwrap();
         }
     }
-    println!("Day 8, part 2: {}", abs_max);
-    assert_eq!(4829, abs_max);
+    let slow = start.elapsed();
+    let compiled = Program::compile(&program);
+    let start = Instant::now();
+    for _ in 0..1000 {
+        compiled.run(&mut compiled.regs()).unwrap();
+    }
+    let quick = start.elapsed();
+    println!("day 8 input x1000: {:?} -> {:?}", slow, quick);
 }
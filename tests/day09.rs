@@ -1,186 +1,308 @@
+use std::io::{self, Read};
+
+use aoc_17::stream::{
+    self,
+    gen::{self, Rng, Shape},
+    Error, Group, Stats, SyntaxError, Totals,
+};
+
 const RAW: &str = include_str!("res/09.txt");
 
-#[derive(Debug)]
-struct Garbage<'a>(&'a [char]);
-
-impl Garbage<'_> {
-    /// assumes that the first character is always '<'
-    fn parse(input: &[char]) -> Garbage {
-        let mut escaped = false;
-        for (index, c) in input.iter().enumerate() {
-            if *c == '!' {
-                escaped = !escaped;
-                continue;
-            } else if escaped {
-                escaped = false;
-                continue;
-            } else if *c == '>' {
-                println!(
-                    "garbage hunk: \"{}\"",
-                    input[..=index].iter().collect::<String>()
-                );
-                return Garbage(&input[..=index]);
-            }
-        }
-        panic!("malformed garbage chunk");
-    }
+#[test]
+fn part1() {
+    let ans = stream::scan(RAW.as_bytes()).unwrap().score;
+    println!("Day 9, part 1: {}", ans);
+    assert_eq!(9662, ans);
+}
 
-    fn size_chars(&self) -> usize {
-        self.0.len()
-    }
+#[test]
+fn test_garbage() {
+    let group = Group::parse(b"{{<>,{<!!!!!>>}},{}}").unwrap();
+    assert_eq!(group.count_garbage_chars(), 0);
+}
 
-    fn valid_chars(&self) -> u32 {
-        let mut count = 0;
-        let mut escaped = false;
-        // let mut escaped_duration = 0;
-        for &c in self.0.iter().skip(1).take(self.0.len() - 2) {
-            if c == '!' {
-                escaped = !escaped;
-                continue;
-            } else if escaped {
-                escaped = false;
-                continue;
-            }
-            count += 1;
-        }
-        return count;
-    }
+/// by both parsers, which had better agree
+fn score(input: &str) -> u32 {
+    let score = Group::parse(input.as_bytes()).unwrap().score(1);
+    assert_eq!(stream::scan(input.as_bytes()).unwrap().score, score as u64);
+    score
+}
+
+#[test]
+fn test_score() {
+    assert_eq!(1, score("{}"));
+    assert_eq!(6, score("{{{}}}"));
+    assert_eq!(5, score("{{},{}}"));
+    assert_eq!(16, score("{{{},{},{{}}}}"));
+    assert_eq!(1, score("{<a>,<a>,<a>,<a>}"));
+    assert_eq!(9, score("{{<ab>},{<ab>},{<ab>},{<ab>}}"));
+    assert_eq!(9, score("{{<!!>},{<!!>},{<!!>},{<!!>}}"));
+    assert_eq!(3, score("{{<a!>},{<a!>},{<a!>},{<ab>}}"));
 }
 
-#[derive(Debug)]
-enum Element<'a> {
-    Group(Group<'a>),
-    Garbage(Garbage<'a>),
+#[test]
+fn part2() {
+    let ans = stream::scan(RAW.as_bytes()).unwrap().garbage;
+    println!("Day 9, part 2: {}", ans);
+    assert_eq!(4903, ans);
 }
 
-impl Element<'_> {
-    fn size_chars(&self) -> usize {
-        match self {
-            Element::Group(g) => g.size_chars(),
-            Element::Garbage(g) => g.size_chars(),
-        }
-    }
+/// Hands out one byte at a time, to make sure nothing depends on where reads
+/// happen to end
+struct Trickle<'a>(&'a [u8]);
 
-    fn parse(input: &[char], depth: usize) -> Element {
-        match input[0] {
-            '{' => Element::Group(Group::parse(input, depth + 1)),
-            '<' => Element::Garbage(Garbage::parse(input)),
-            other => {
-                println!(
-                    "Panicking on char: '{}' for slice \"{}...\"",
-                    other,
-                    input.iter().take(10).collect::<String>()
-                );
-                panic!("Bad input for element parsing")
+impl Read for Trickle<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self.0.split_first() {
+            Some((&byte, rest)) if !buf.is_empty() => {
+                buf[0] = byte;
+                self.0 = rest;
+                Ok(1)
             }
+            _ => Ok(0),
         }
     }
+}
 
-    fn score(&self, depth: u32) -> u32 {
-        match self {
-            Element::Group(g) => g.score(depth),
-            Element::Garbage(_) => 0,
-        }
-    }
+#[test]
+fn test_scan() {
+    let group = Group::parse(RAW.as_bytes()).unwrap();
+    let totals = stream::scan(Trickle(RAW.as_bytes())).unwrap();
+    assert_eq!(totals.score, group.score(1) as u64);
+    assert_eq!(totals.garbage, group.count_garbage_chars() as u64);
+    assert_eq!(totals.groups as usize, group.paths().len());
 
-    fn count_garbage_chars(&self) -> u32 {
-        match self {
-            Element::Group(g) => g.count_garbage_chars(),
-            Element::Garbage(g) => g.valid_chars(),
-        }
-    }
-}
+    let totals = stream::scan("{{<a!>},{<!!>,<x>},{}}\n".as_bytes()).unwrap();
+    let expected = Totals {
+        score: 5,
+        groups: 3,
+        garbage: 6,
+        max_depth: 2,
+        len: 22,
+    };
+    assert_eq!(totals, expected);
 
-#[derive(Debug)]
-struct Group<'a>(Vec<Element<'a>>);
+    // garbage is counted in bytes
+    let totals = stream::scan("{<é>}".as_bytes()).unwrap();
+    assert_eq!(totals.garbage, 2);
 
-impl Group<'_> {
-    /// assumes the first character is always '{'
-    fn parse(input: &[char], depth: usize) -> Group<'_> {
-        println!(
-            "group input: \"{}...\"",
-            input.iter().take(10).collect::<String>()
-        );
-        let mut elements: Vec<Element> = Vec::new();
-        let mut i = 1;
-        while i < input.len() {
-            if input[i] == '}' {
-                break;
-            }
-            if input[i] == ',' {
-                i += 1;
-            }
-            let element = Element::parse(&input[i..], depth + 1);
-            i += element.size_chars();
-            elements.push(element);
-        }
-        Group(elements)
-    }
+    // far deeper than the recursive parser could go
+    let depth = 1_000_000;
+    let deep = "{".repeat(depth) + "<!>>" + &"}".repeat(depth);
+    let totals = stream::scan(deep.as_bytes()).unwrap();
+    assert_eq!(totals.max_depth, depth);
+    assert_eq!(totals.score, (depth * (depth + 1) / 2) as u64);
+    assert_eq!(totals.garbage, 0);
+    // the tree can be built and thrown away just as deep
+    let group = Group::parse(deep.as_bytes()).unwrap();
+    assert_eq!(group.groups().count(), 1);
+    drop(group);
 
-    fn size_chars(&self) -> usize {
-        let num_commas = if self.0.len() == 0 {
-            0
-        } else {
-            self.0.len() - 1
-        };
-        self.0.iter().map(Element::size_chars).sum::<usize>() + num_commas + 2
+    for bad in ["", "{", "{<}", "{}}", "{,}", "{{},}", "<>", "{}x"] {
+        assert!(stream::scan(bad.as_bytes()).is_err(), "{:?}", bad);
     }
+}
 
-    fn score(&self, depth: u32) -> u32 {
-        depth + self.0.iter().map(|e| e.score(depth + 1)).sum::<u32>()
+fn syntax_error(input: &str) -> SyntaxError {
+    match stream::scan(input.as_bytes()) {
+        Err(Error::Syntax(err)) => err,
+        other => panic!("{:?} for {:?}", other, input),
     }
+}
 
-    fn count_garbage_chars(&self) -> u32 {
-        self.0.iter().map(Element::count_garbage_chars).sum()
-    }
+#[test]
+fn test_errors() {
+    let err = syntax_error("{{},{<\n>},{{<x>\n<y>}}}");
+    let expected = SyntaxError {
+        offset: 15,
+        line: 2,
+        col: 9,
+        path: vec![0, 2, 0],
+        expected: "',' or '}'",
+        found: Some(b'\n'),
+    };
+    assert_eq!(err, expected);
+    assert_eq!(
+        err.to_string(),
+        "line 2, column 9, in group 0.2.0: expected ',' or '}', found '\\n'"
+    );
+
+    // unclosed groups and garbage
+    let err = syntax_error("{{},{<a>");
+    assert_eq!((err.offset, err.line, err.col), (8, 1, 9));
+    assert_eq!(err.path, [0, 1]);
+    assert_eq!(err.found, None);
+    assert_eq!(
+        err.to_string(),
+        "line 1, column 9, in group 0.1: expected ',' or '}', found the end of the stream"
+    );
+    assert_eq!(syntax_error("{<!>}").expected, "'>'");
+    assert_eq!(syntax_error("{<!").expected, "a character to cancel");
+
+    // anything but whitespace after the outermost group
+    let err = syntax_error("{}\n{}");
+    assert_eq!(err.path, []);
+    assert_eq!(
+        err.to_string(),
+        "line 2, column 1: expected the end of the stream, found '{'"
+    );
+    let err = syntax_error("x");
+    assert_eq!(err.to_string(), "line 1, column 1: expected '{', found 'x'");
+    assert_eq!(syntax_error("{,}").expected, "'{', '<' or '}'");
+    assert_eq!(syntax_error("{{},}").expected, "'{' or '<'");
+
+    // a byte that isn't ASCII is shown as it is, not as a character
+    let err = syntax_error("{é}");
+    assert_eq!(err.found, Some(0xc3));
+    assert_eq!(
+        err.to_string(),
+        "line 1, column 2, in group 0: expected '{', '<' or '}', found 0xc3"
+    );
 }
 
 #[test]
-fn part1() {
-    let input: Vec<_> = RAW.chars().collect();
-    let group = Group::parse(&input, 1);
-    let ans = group.score(1);
-    println!("Day 9, part 1: {}", ans);
-    assert_eq!(9662, ans);
+fn test_tree() {
+    let input = "{{<a!>},{<!!>,<x>},{}},{{},<>}}";
+    assert!(Group::parse(input.as_bytes()).is_err());
+
+    let input = "{{<a!>},{<!!>,<x>},{},{{{}},<>,{}},{}}\n";
+    let group = Group::parse(input.as_bytes()).unwrap();
+    assert_eq!(group.to_string(), input.trim_end());
+    assert_eq!(group.size_chars(), input.trim_end().len());
+    assert_eq!(group.canonical(), "{{},{},{{{}},{}},{}}");
+
+    let expected = "\
+{
+    {
+        <a!>},{<!!>,
+        <x>
+    },
+    {},
+    {
+        {
+            {}
+        },
+        <>,
+        {}
+    },
+    {}
+}";
+    assert_eq!(group.pretty(), expected);
+
+    let paths = group.paths();
+    let expected: Vec<Vec<usize>> = vec![
+        vec![],
+        vec![0],
+        vec![1],
+        vec![2],
+        vec![2, 0],
+        vec![2, 0, 0],
+        vec![2, 1],
+        vec![3],
+    ];
+    assert_eq!(paths, expected);
+    assert_eq!(group.get(&[2, 0]).unwrap().canonical(), "{{}}");
+    assert_eq!(group.get(&[2, 2]), None);
+    let stats = |depth, groups, hunks, garbage| Stats {
+        depth,
+        groups,
+        hunks,
+        garbage,
+    };
+    assert_eq!(group.stats(&[]), Some(stats(1, 4, 0, 0)));
+    assert_eq!(group.stats(&[0]), Some(stats(2, 0, 2, 6)));
+    assert_eq!(group.stats(&[2]), Some(stats(2, 2, 1, 0)));
+    assert_eq!(group.stats(&[2, 0, 0]), Some(stats(4, 0, 0, 0)));
+    assert_eq!(group.stats(&[4]), None);
+
+    // a syntax error's path leads to the group it happened in
+    let err = match stream::scan("{{},{},{{<a>,x}}}".as_bytes()) {
+        Err(Error::Syntax(err)) => err,
+        other => panic!("{:?}", other),
+    };
+    let group = Group::parse(b"{{},{},{{<a>}}}").unwrap();
+    assert_eq!(group.get(&err.path[1..]).unwrap().to_string(), "{<a>}");
 }
 
 #[test]
-fn test_garbage() {
-    let input = "{{<>,{<!!!!!>>}},{}}".chars().collect::<Vec<char>>();
-    println!("{:?}", Group::parse(&input, 1));
+fn test_generate() {
+    // no seed gets stuck at 0
+    let mut rng = Rng::new(0x9e37_79b9_7f4a_7c15);
+    assert!((0..4).any(|_| rng.next_u64() != 0));
+
+    let mut rng = Rng::new(9);
+    for _ in 0..500 {
+        let (input, expected) = gen::stream(&mut rng, Shape::default());
+        assert_eq!(
+            stream::scan(input.as_bytes()).unwrap(),
+            expected,
+            "{}",
+            input
+        );
+        let group = Group::parse(input.as_bytes()).unwrap();
+        assert_eq!(group.score(1) as u64, expected.score);
+        assert_eq!(group.count_garbage_chars() as u64, expected.garbage);
+        assert_eq!(group.size_chars(), input.len());
+        assert_eq!(group.to_string(), input);
+        let canonical = group.canonical();
+        let stripped = stream::scan(canonical.as_bytes()).unwrap();
+        assert_eq!((stripped.score, stripped.garbage), (expected.score, 0));
+    }
+    let deep = Shape {
+        max_depth: 200,
+        max_elements: 2,
+        max_garbage: 0,
+    };
+    let (input, expected) = gen::stream(&mut rng, deep);
+    assert_eq!(stream::scan(input.as_bytes()).unwrap(), expected);
+}
+
+/// Both parsers have to agree on everything, and neither can panic
+fn check(input: &[u8]) {
+    match (stream::scan(input), Group::parse(input)) {
+        (Ok(totals), Ok(group)) => {
+            assert_eq!(group.size_chars(), totals.len);
+            assert!(input[totals.len..].iter().all(u8::is_ascii_whitespace));
+            assert_eq!(group.score(1) as u64, totals.score);
+            assert_eq!(group.count_garbage_chars() as u64, totals.garbage);
+            if std::str::from_utf8(input).is_ok() {
+                assert_eq!(group.to_string().as_bytes(), &input[..totals.len]);
+            }
+        }
+        (Err(Error::Syntax(a)), Err(b)) => {
+            assert_eq!(a, b);
+            assert!(a.offset <= input.len());
+        }
+        (a, b) => panic!("{:?} but {:?}", a, b),
+    }
+}
+
+fn fuzz(cases: usize) {
+    let mut rng = Rng::new(42);
+    for _ in 0..cases {
+        let len = rng.below(40);
+        check(&gen::noise(&mut rng, len));
+        // valid streams with one thing changed get much further
+        let (input, _) = gen::stream(&mut rng, Shape::default());
+        let mut input = input.into_bytes();
+        let i = rng.below(input.len());
+        match rng.below(3) {
+            0 => input[i] = gen::noise(&mut rng, 1)[0],
+            1 => input.insert(i, gen::noise(&mut rng, 1)[0]),
+            _ => drop(input.remove(i)),
+        }
+        check(&input);
+    }
 }
 
 #[test]
-fn test_score() {
-    let input = "{}".chars().collect::<Vec<char>>();
-    assert_eq!(1, Group::parse(&input, 1).score(1));
-    let input = "{{{}}}".chars().collect::<Vec<char>>();
-    assert_eq!(6, Group::parse(&input, 1).score(1));
-    let input = "{{},{}}".chars().collect::<Vec<char>>();
-    assert_eq!(5, Group::parse(&input, 1).score(1));
-    let input = "{{{},{},{{}}}}".chars().collect::<Vec<char>>();
-    assert_eq!(16, Group::parse(&input, 1).score(1));
-    let input = "{<a>,<a>,<a>,<a>}".chars().collect::<Vec<char>>();
-    assert_eq!(1, Group::parse(&input, 1).score(1));
-    let input = "{{<ab>},{<ab>},{<ab>},{<ab>}}"
-        .chars()
-        .collect::<Vec<char>>();
-    assert_eq!(9, Group::parse(&input, 1).score(1));
-    let input = "{{<!!>},{<!!>},{<!!>},{<!!>}}"
-        .chars()
-        .collect::<Vec<char>>();
-    assert_eq!(9, Group::parse(&input, 1).score(1));
-    let input = "{{<a!>},{<a!>},{<a!>},{<ab>}}"
-        .chars()
-        .collect::<Vec<char>>();
-    assert_eq!(3, Group::parse(&input, 1).score(1));
+fn test_fuzz() {
+    fuzz(2000);
 }
 
+/// `cargo test --release --test day09 fuzz_long -- --ignored`
 #[test]
-fn part2() {
-    let input: Vec<_> = RAW.chars().collect();
-    let group = Group::parse(&input, 1);
-    let ans = group.count_garbage_chars();
-    println!("Day 9, part 2: {}", ans);
-    assert_eq!(4903, ans);
+#[ignore]
+fn fuzz_long() {
+    fuzz(1_000_000);
 }
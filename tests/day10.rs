@@ -1,49 +1,95 @@
-fn twist<const N: usize>(pos: usize, length: usize, state: &mut [u32; N]) {
-    let to_swap: Vec<u32> = state
-        .iter()
-        .cycle()
-        .skip(pos)
-        .take(length)
-        .cloned()
-        .collect();
-    for (i, val) in to_swap.into_iter().rev().enumerate() {
-        state[(pos + i) % N] = val
-    }
-}
+use std::{
+    collections::HashMap,
+    hash::{BuildHasher, Hasher},
+};
+
+use aoc_17::knot::{dense_hash, BuildKnotHasher, KnotHash, KnotHashParams, KnotHasher};
+
+const VECTORS: [(&str, &str); 4] = [
+    ("", "a2582a3a0e66e6e86e3812dcb672a272"),
+    ("AoC 2017", "33efeb34ea91902bb2f59c9920caa6cd"),
+    ("1,2,3", "3efbe78a8d82f29979031a4aa0b16a9d"),
+    ("1,2,4", "63960835bcdc130f0b66d7ff4f6a5a8e"),
+];
+
+const INPUT: &str = "225,171,131,2,35,5,0,13,1,246,54,97,255,98,254,110";
 
 #[test]
 fn part1() {
-    let input: Vec<usize> = vec![
-        225, 171, 131, 2, 35, 5, 0, 13, 1, 246, 54, 97, 255, 98, 254, 110,
-    ];
-    let mut state = [0u32; 256];
-    for i in 0..256 {
-        state[i] = i as u32;
-    }
-    let mut pos = 0;
-    for (skip_size, &length) in input.iter().enumerate() {
-        twist(pos, length, &mut state);
-        pos = (pos + length + skip_size) % 256;
-    }
-    let ans = state[0] * state[1];
+    let input: Vec<usize> = INPUT.split(',').map(|n| n.parse().unwrap()).collect();
+    let ans = KnotHash::sparse(&input, 1).unwrap().product();
     println!("Day 10, part 1: {}", ans);
     assert_eq!(23874, ans);
 }
 
-fn knot_hash_round<const N: usize>(
-    pos: &mut usize,
-    skip: usize,
-    state: &mut [u32; N],
-    input: &[usize],
-) {
-    for (base_skip, &length) in input.iter().enumerate() {
-        twist(*pos, length, state);
-        *pos = (*pos + length + base_skip + skip) % N;
-    }
+#[test]
+fn test_example() {
+    let params = KnotHashParams {
+        size: 5,
+        rounds: 1,
+        suffix: Vec::new(),
+        block: 5,
+    };
+    let hash = params.sparse(&[3, 4, 1, 5]).unwrap();
+    assert_eq!(hash.state, [3, 4, 2, 1, 0]);
+    assert_eq!((hash.pos, hash.skip), (4, 4));
+    assert_eq!(hash.product(), 12);
+    assert_eq!(hash.digest(), [3 ^ 4 ^ 2 ^ 1]);
+    assert_eq!(
+        params.sparse(&[3, 6]),
+        Err("length 6 is longer than the ring of 5".to_string())
+    );
 }
 
-fn dense_hash(state: &[u32]) -> u32 {
-    state.into_iter().fold(0, |a, b| a ^ *b)
+#[test]
+fn test_params() {
+    let params = KnotHashParams::default();
+    assert_eq!(params.validate(), Ok(()));
+    for (input, hex) in VECTORS.iter() {
+        assert_eq!(
+            params.hash(input).unwrap(),
+            input.parse::<KnotHash>().unwrap()
+        );
+        assert_eq!(params.hash(input).unwrap().to_hex(), *hex);
+    }
+
+    // a smaller ring and more rounds still hash, just differently
+    let small = KnotHashParams {
+        size: 128,
+        rounds: 16,
+        block: 16,
+        ..KnotHashParams::default()
+    };
+    let hash = small.hash("1,2,3").unwrap();
+    assert_eq!(hash.digest().len(), 8);
+    assert_ne!(hash.digest(), small.hash("1,2,4").unwrap().digest());
+    // the bytes of the input are lengths too, and 'é' is 195 and 169
+    assert!(small.hash("café").is_err());
+
+    let bad = |size, block, suffix: &[usize]| KnotHashParams {
+        size,
+        rounds: 1,
+        suffix: suffix.to_vec(),
+        block,
+    };
+    let errors = [
+        (bad(0, 1, &[]), "ring size 0 isn't from 1 to 256"),
+        (bad(257, 1, &[]), "ring size 257 isn't from 1 to 256"),
+        (bad(10, 0, &[]), "block of 0 doesn't divide the ring of 10"),
+        (bad(10, 4, &[]), "block of 4 doesn't divide the ring of 10"),
+        (
+            bad(10, 5, &[3, 11]),
+            "length 11 is longer than the ring of 10",
+        ),
+    ];
+    for (params, msg) in errors.iter() {
+        assert_eq!(params.validate(), Err(msg.to_string()));
+        assert_eq!(params.sparse(&[]), Err(msg.to_string()));
+    }
+    assert_eq!(
+        KnotHash::sparse(&[300], 1),
+        Err("length 300 is longer than the ring of 256".to_string())
+    );
 }
 
 #[test]
@@ -55,25 +101,42 @@ fn test_dense_hash() {
 }
 
 #[test]
-fn part2() {
-    let input = "225,171,131,2,35,5,0,13,1,246,54,97,255,98,254,110".as_bytes();
-    let mut extra = vec![17, 31, 73, 47, 23];
-    let mut input: Vec<_> = input.iter().map(|byte| *byte as usize).collect();
-    input.append(&mut extra);
-    let (mut pos, mut skip) = (0, 0);
-    let mut state = [0u32; 256];
-    for i in 0..256 {
-        state[i] = i as u32;
-    }
-    for _ in 0..64 {
-        knot_hash_round(&mut pos, skip, &mut state, &input);
-        skip += input.len();
+fn test_knot_hash() {
+    for (input, hex) in VECTORS.iter() {
+        let hash: KnotHash = input.parse().unwrap();
+        assert_eq!(hash.to_hex(), *hex);
+        assert_eq!(format!("{:032x}", hash.dense()), *hex);
     }
-    let ans: String = state
-        .chunks(16)
-        .map(dense_hash)
-        .map(|n| format!("{:02x}", n))
-        .collect();
+}
+
+#[test]
+fn part2() {
+    let ans = INPUT.parse::<KnotHash>().unwrap().to_hex();
     println!("Day 10, part 2: {}", ans);
     assert_eq!(String::from("e1a65bfb5a5ce396025fab5528c25a87"), ans);
 }
+
+#[test]
+fn test_hasher() {
+    for (input, hex) in VECTORS.iter() {
+        // split up any old way, it's the same as one write
+        let mut hasher = KnotHasher::new();
+        for chunk in input.as_bytes().chunks(3) {
+            hasher.write(chunk);
+        }
+        assert_eq!(hasher.digest().to_hex(), *hex);
+        assert_eq!(format!("{:032x}", hasher.finish128()), *hex);
+        let low = u64::from_str_radix(&hex[16..], 16).unwrap();
+        assert_eq!(hasher.finish(), low);
+    }
+
+    let build = BuildKnotHasher::default();
+    assert_eq!(build.hash_one("1,2,3"), build.hash_one("1,2,3"));
+    assert_ne!(build.hash_one("1,2,3"), build.hash_one("1,2,4"));
+    let mut map = HashMap::with_hasher(build);
+    for (input, hex) in VECTORS.iter() {
+        map.insert(*input, *hex);
+    }
+    assert_eq!(map.len(), 4);
+    assert_eq!(map["AoC 2017"], "33efeb34ea91902bb2f59c9920caa6cd");
+}
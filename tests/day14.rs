@@ -1,6 +1,167 @@
-use std::convert::TryInto;
+use std::time::Instant;
 
-fn twist<const N: usize>(pos: usize, length: usize, state: &mut [u8; N]) {
+use aoc_17::{
+    disk::{self, hash_row, regions, Bounds, DiskGrid, Region},
+    knot::{self, KnotHash},
+};
+
+#[test]
+fn part1() {
+    // spell-checker: disable
+    let ans = DiskGrid::new("stpzcrnm").used();
+    println!("Day 14, part 1: {}", ans);
+    assert_eq!(8250, ans);
+}
+
+#[test]
+fn part2() {
+    // spell-checker: disable
+    let ans = DiskGrid::new("stpzcrnm").regions();
+    println!("Day 14, part 2: {}", ans);
+    assert_eq!(1113, ans)
+}
+
+#[test]
+fn test_example() {
+    // spell-checker: disable
+    let disk = DiskGrid::new("flqrgnkx");
+    assert_eq!(disk.used(), 8108);
+    assert_eq!(disk.regions(), 1242);
+    let corner = "\
+##.#.#..
+.#.#.#.#
+....#.#.
+#.#.##.#
+.##.#...
+##..#..#
+.#...#..
+##.#.##.";
+    assert_eq!(disk.render(8, 8), corner);
+    assert_eq!(disk.to_string().lines().count(), 128);
+    assert!(disk.to_string().lines().all(|line| line.len() == 128));
+    assert!(disk.is_used(0, 0) && !disk.is_used(0, 2));
+    assert!(!disk.is_used(0, 128) && !disk.is_used(128, 0));
+    assert_eq!(disk.region(0, 0), Some(0));
+    assert_eq!(disk.region(0, 1), Some(0));
+    assert_eq!(disk.region(1, 1), Some(0));
+    assert_eq!(disk.region(0, 2), None);
+    assert_ne!(disk.region(0, 3), disk.region(0, 0));
+    // the two halves of region 4 in the puzzle's picture meet on row 4
+    assert_eq!(disk.region(3, 4), disk.region(4, 4));
+    assert_eq!(disk.region(3, 5), disk.region(3, 4));
+}
+
+#[test]
+fn test_images() {
+    let disk = DiskGrid::from_rows(vec![0b11 << 126, 0b1 << 120]);
+    // everything after the header, one value at a time
+    let values = |image: &str, header: usize| -> Vec<u8> {
+        assert!(image.lines().all(|line| line.len() <= 70));
+        let lines: Vec<_> = image.lines().skip(header).collect();
+        lines
+            .join(" ")
+            .split(' ')
+            .map(|v| v.parse().unwrap())
+            .collect()
+    };
+
+    let mut pbm = Vec::new();
+    disk.write_pbm(&mut pbm, 1).unwrap();
+    let pbm = String::from_utf8(pbm).unwrap();
+    let lines: Vec<_> = pbm.lines().collect();
+    assert_eq!(lines[..2], ["P1", "128 2"]);
+    let bits = values(&pbm, 2);
+    assert_eq!(bits.len(), 128 * 2);
+    assert_eq!(bits[..9], [1, 1, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(bits[128..137], [0, 0, 0, 0, 0, 0, 0, 1, 0]);
+
+    let mut ppm = Vec::new();
+    disk.write_ppm(&mut ppm, 2).unwrap();
+    let ppm = String::from_utf8(ppm).unwrap();
+    let lines: Vec<_> = ppm.lines().collect();
+    assert_eq!(lines[..3], ["P3", "256 4", "255"]);
+    let pixels: Vec<_> = values(&ppm, 3).chunks(3).map(|rgb| rgb.to_vec()).collect();
+    assert_eq!(pixels.len(), 256 * 4);
+    let (top, bottom) = (&pixels[..256], &pixels[512..768]);
+    assert_eq!(top[0], top[3]);
+    assert_eq!(top[4], [0, 0, 0]);
+    assert_ne!(bottom[14], [0, 0, 0]);
+    assert_ne!(bottom[14], top[0]);
+}
+
+#[test]
+fn test_regions() {
+    // spell-checker: disable
+    for key in ["flqrgnkx", "stpzcrnm"] {
+        let disk = DiskGrid::new(key);
+        let used: Vec<bool> = (0..128 * 128)
+            .map(|i| disk.is_used(i / 128, i % 128))
+            .collect();
+        let filled = regions::flood_fill(&used, 128);
+        assert_eq!(&filled, disk.labels());
+        assert_eq!(regions::union_find(&used, 128), filled);
+        let total: usize = filled.regions.iter().map(|r| r.size).sum();
+        assert_eq!(total, disk.used() as usize);
+    }
+
+    // not square, and a U shape that only joins up at the bottom
+    let grid = "\
+#.#..##
+#.#...#
+###.#..";
+    let used: Vec<bool> = grid
+        .lines()
+        .flat_map(|l| l.chars())
+        .map(|c| c == '#')
+        .collect();
+    let filled = regions::flood_fill(&used, 7);
+    assert_eq!(regions::union_find(&used, 7), filled);
+    let region = |size, top, left, bottom, right| Region {
+        size,
+        bounds: Bounds {
+            top,
+            left,
+            bottom,
+            right,
+        },
+    };
+    let expected = [
+        region(7, 0, 0, 2, 2),
+        region(3, 0, 5, 1, 6),
+        region(1, 2, 4, 2, 4),
+    ];
+    assert_eq!(filled.regions, expected);
+    assert_eq!(filled.label(0, 2), Some(0));
+    assert_eq!(filled.label(1, 6), Some(1));
+    assert_eq!(filled.label(0, 3), None);
+    assert_eq!(filled.label(0, 7), None);
+    assert_eq!(filled.label(3, 0), None);
+
+    // one region far too big to recurse through
+    let side = 1000;
+    let used = vec![true; side * side];
+    let filled = regions::flood_fill(&used, side);
+    assert_eq!(
+        filled.regions,
+        [region(side * side, 0, 0, side - 1, side - 1)]
+    );
+    assert_eq!(regions::union_find(&used, side), filled);
+}
+
+#[test]
+#[should_panic(expected = "2 squares don't make rows of 0")]
+fn test_regions_no_width() {
+    regions::flood_fill(&[true, false], 0);
+}
+
+#[test]
+#[should_panic(expected = "3 squares don't make rows of 2")]
+fn test_regions_ragged() {
+    regions::union_find(&[true, false, true], 2);
+}
+
+/// How `twist` used to work, copying the run out and writing it back reversed
+fn twist_copy<const N: usize>(pos: usize, length: usize, state: &mut [u8; N]) {
     let to_swap: Vec<u8> = state
         .iter()
         .cycle()
@@ -13,119 +174,95 @@ fn twist<const N: usize>(pos: usize, length: usize, state: &mut [u8; N]) {
     }
 }
 
-fn knot_hash_round<const N: usize>(
-    pos: &mut usize,
-    skip: usize,
-    state: &mut [u8; N],
-    input: &[usize],
-) {
-    for (base_skip, &length) in input.iter().enumerate() {
-        twist(*pos, length, state);
-        *pos = (*pos + length + base_skip + skip) % N;
-    }
-}
-
-fn dense_hash(state: &[u8]) -> u8 {
-    state.into_iter().fold(0, |a, b| a ^ *b)
-}
-
-fn knot_hash(input: &[usize]) -> u128 {
-    let (mut pos, mut skip) = (0, 0);
+/// `hash_row`, but with `twist_copy`
+fn row_copy(seed: &str, i: usize) -> u128 {
+    let input = format!("{}-{}", seed, i);
+    let mut lengths: Vec<_> = input.bytes().map(usize::from).collect();
+    lengths.extend_from_slice(&knot::SUFFIX);
     let mut state = [0; 256];
-    for i in 0..256 {
-        state[i] = i as u8;
+    for (i, mark) in state.iter_mut().enumerate() {
+        *mark = i as u8;
     }
-    for _ in 0..64 {
-        knot_hash_round(&mut pos, skip, &mut state, input);
-        skip += input.len();
+    let (mut pos, mut skip) = (0, 0);
+    for _ in 0..knot::ROUNDS {
+        for &length in &lengths {
+            twist_copy(pos, length, &mut state);
+            pos = (pos + length + skip) % 256;
+            skip += 1;
+        }
     }
-    let bytes: Vec<_> = state.chunks(16).map(dense_hash).rev().collect();
-    let bytes: [u8; 16] = bytes.try_into().unwrap();
-    u128::from_le_bytes(bytes)
+    let hash = KnotHash {
+        state: state.to_vec(),
+        ..KnotHash::default()
+    };
+    hash.dense()
 }
 
 #[test]
-fn test() {
-    let input = "225,171,131,2,35,5,0,13,1,246,54,97,255,98,254,110".as_bytes();
-    let mut extra = vec![17, 31, 73, 47, 23];
-    let mut input: Vec<_> = input.iter().map(|byte| *byte as usize).collect();
-    input.append(&mut extra);
-    println!("{:x}", knot_hash(&input))
+fn test_twist() {
+    for pos in 0..7 {
+        for length in 0..=7 {
+            let mut a = [0, 1, 2, 3, 4, 5, 6];
+            let mut b = a;
+            knot::twist(pos, length, &mut a);
+            twist_copy(pos, length, &mut b);
+            assert_eq!(a, b, "twist({}, {})", pos, length);
+        }
+    }
+    assert_eq!(hash_row("stpzcrnm", 5), row_copy("stpzcrnm", 5));
 }
 
+/// `cargo test --release --test day14 bench_twist -- --ignored --nocapture`
 #[test]
-fn part1() {
-    // spell-checker: disable
-    let seed = "stpzcrnm";
-    let extra = vec![17, 31, 73, 47, 23];
-    let hashes: u32 = (0..128)
-        .map(|i| format!("{}-{}", seed, i))
-        .map(|s| s.as_str().bytes().map(|b| b as usize).collect())
-        .map(|mut input: Vec<_>| {
-            input.append(&mut extra.clone());
-            input
-        })
-        .map(|input| knot_hash(&input))
-        .map(|h| h.count_ones())
-        .sum();
-    println!("Day 14, part 1: {}", hashes);
-    assert_eq!(8250, hashes);
-}
-
-#[derive(Debug, Clone, Copy)]
-enum Point {
-    Empty,
-    Unset,
-    Region(u32),
+#[ignore]
+fn bench_twist() {
+    let start = Instant::now();
+    let slow: Vec<_> = (0..128).map(|i| row_copy("stpzcrnm", i)).collect();
+    let slow_time = start.elapsed();
+    let start = Instant::now();
+    let quick: Vec<_> = (0..128).map(|i| hash_row("stpzcrnm", i)).collect();
+    let quick_time = start.elapsed();
+    assert_eq!(slow, quick);
+    println!("128 rows: {:?} -> {:?}", slow_time, quick_time);
 }
 
-fn flood_fill<const N: usize>(grid: &mut [[Point; N]], region: u32, i: usize, j: usize) {
-    if let Point::Unset = grid[i][j] {
-        grid[i][j] = Point::Region(region);
-    }
-    for (di, dj) in [(-1, 0), (0, -1), (1, 0), (0, 1)] {
-        let r = (i as isize + di) as usize;
-        let c = (j as isize + dj) as usize;
-        if let Some(Point::Unset) = grid.get(r).and_then(|g| g.get(c)) {
-            flood_fill(grid, region, r, c)
-        }
+#[test]
+fn test_parallel() {
+    // spell-checker: disable
+    let serial: Vec<_> = (0..20).map(|i| hash_row("flqrgnkx", i)).collect();
+    for threads in [0, 1, 2, 3, 7, 20, 100] {
+        assert_eq!(
+            disk::hash_rows("flqrgnkx", 20, threads),
+            serial,
+            "{}",
+            threads
+        );
     }
+    assert_eq!(disk::hash_rows("flqrgnkx", 0, 4), []);
+
+    let tall = DiskGrid::with_rows("flqrgnkx", 140);
+    let disk = DiskGrid::new("flqrgnkx");
+    assert_eq!(tall.height(), 140);
+    assert_eq!(tall.rows[..128], disk.rows[..]);
+    assert_eq!(tall.rows[139], hash_row("flqrgnkx", 139));
+    assert!(tall.used() > disk.used());
 }
 
+/// `cargo test --release --test day14 bench_rows -- --ignored --nocapture`
 #[test]
-fn part2() {
-    // spell-checker: disable
-    let seed = "stpzcrnm";
-    let extra = vec![17, 31, 73, 47, 23];
-    // create data
-    let data: Vec<u128> = (0..128)
-        .map(|i| format!("{}-{}", seed, i))
-        .map(|s| s.as_str().bytes().map(|b| b as usize).collect())
-        .map(|mut input: Vec<_>| {
-            input.append(&mut extra.clone());
-            input
-        })
-        .map(|input| knot_hash(&input))
-        .collect();
-    // populate grid
-    let mut grid = [[Point::Empty; 128]; 128];
-    for (i, d) in data.iter().enumerate() {
-        for j in 0..128 {
-            if d & (1 << j) != 0 {
-                grid[i][j] = Point::Unset;
-            }
-        }
-    }
-    let mut region = 0;
-    // define regions
-    for i in 0..128 {
-        for j in 0..128 {
-            if let Point::Unset = grid[i][j] {
-                flood_fill(&mut grid, region, i, j);
-                region += 1;
-            }
-        }
-    }
-    println!("Day 14, part 2: {}", region);
-    assert_eq!(1113, region)
+#[ignore]
+fn bench_rows() {
+    let rows = 128 * 16;
+    let start = Instant::now();
+    let serial = disk::hash_rows("stpzcrnm", rows, 1);
+    let serial_time = start.elapsed();
+    let threads = disk::threads();
+    let start = Instant::now();
+    let parallel = disk::hash_rows("stpzcrnm", rows, threads);
+    let parallel_time = start.elapsed();
+    assert_eq!(serial, parallel);
+    println!(
+        "{} rows: {:?} on 1 thread -> {:?} on {}",
+        rows, serial_time, parallel_time, threads
+    );
 }
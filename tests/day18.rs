@@ -63,176 +63,394 @@
 //! played sound) the first time a rcv instruction is executed with a non-zero
 //! value?
 
-use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+use aoc_17::duet::{
+    asm, cfg, fast, run_threaded, Breakpoint, Comp, Debugger, MessageQueue, Network, Op, Outcome,
+    Queue, SoundCard, Stop, Topology,
+};
+use std::{collections::VecDeque, env, fs, time::Instant};
 
 const INPUT: &str = include_str!("res/18.txt");
 
-#[derive(Debug)]
-enum Arg {
-    Val(i64),
-    Reg(usize),
-}
-
-impl Arg {
-    fn parse(token: &&str) -> Arg {
-        match token.parse() {
-            Ok(val) => Arg::Val(val),
-            Err(_) => Arg::Reg(
-                token
-                    .chars()
-                    .next()
-                    .map(|c| c as usize - 'a' as usize)
-                    .unwrap(),
-            ),
-        }
+/// Panics unless `line` is a single valid instruction
+fn op(line: &str) -> Op {
+    line.parse().unwrap()
+}
+
+fn recover(program: &[Op]) -> Option<i64> {
+    let mut comp = Comp::new(program, 0, SoundCard::default());
+    while comp.device.recovered.is_none() && !comp.terminated() {
+        comp.step();
     }
+    comp.device.recovered
 }
 
-#[derive(Debug)]
-enum Op {
-    Snd(usize),
-    Rcv(usize),
-    Set(usize, Arg),
-    Add(usize, Arg),
-    Mul(usize, Arg),
-    Mod(usize, Arg),
-    Jgz(usize, Arg),
-}
-
-impl Op {
-    fn parse(input: &str) -> Op {
-        let tokens: Vec<_> = input.split(' ').collect();
-        let reg = match Arg::parse(&tokens[1]) {
-            Arg::Val(v) => v as usize,
-            Arg::Reg(r) => r,
-        };
-        let val: Option<Arg> = tokens.get(2).map(Arg::parse);
-        match tokens[0] {
-            "snd" => Op::Snd(reg),
-            "rcv" => Op::Rcv(reg),
-            "set" => Op::Set(reg, val.unwrap()),
-            "add" => Op::Add(reg, val.unwrap()),
-            "mul" => Op::Mul(reg, val.unwrap()),
-            "mod" => Op::Mod(reg, val.unwrap()),
-            "jgz" => Op::Jgz(reg, val.unwrap()),
-            _ => panic!("Unidentified token"),
-        }
+#[test]
+fn test_sound_card() {
+    let program: Vec<_> = include_str!("res/18ex.txt").lines().map(op).collect();
+    assert_eq!(Some(4), recover(&program));
+}
+
+#[test]
+fn part1() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let ans = recover(&program).unwrap();
+    println!("Day 18, part 1: {}", ans);
+    assert_eq!(8600, ans);
+}
+
+#[test]
+fn test_debugger() {
+    let program: Vec<_> = include_str!("res/18ex.txt").lines().map(op).collect();
+    let mut debugger = Debugger::new(Comp::new(&program, 0, SoundCard::default()));
+    debugger.add_breakpoint(Breakpoint::parse("6").unwrap());
+    assert_eq!(Stop::Breakpoint(0), debugger.cont().unwrap());
+    assert_eq!((6, 6), (debugger.comp.pos, debugger.comp.steps));
+    assert_eq!(Some(4), debugger.comp.device.last_played);
+    debugger.add_breakpoint(Breakpoint::parse("a == 1").unwrap());
+    assert_eq!(Stop::Breakpoint(1), debugger.cont().unwrap());
+    assert_eq!(9, debugger.comp.pos);
+    assert_eq!(None, debugger.step().unwrap());
+    assert_eq!(7, debugger.comp.pos);
+    assert!(Breakpoint::parse("z > 1").is_err());
+}
+
+#[test]
+fn test_debugger_recover() {
+    let program: Vec<_> = include_str!("res/18ex.txt").lines().map(op).collect();
+    let mut debugger = Debugger::new(Comp::new(&program, 0, SoundCard::default()));
+    assert_eq!(
+        debugger.command("run"),
+        Ok(String::from(
+            "halted at pc 7\nlast played: Some(4), recovered: Some(4)"
+        ))
+    );
+    assert_eq!(Stop::Halted, debugger.cont().unwrap());
+    assert_eq!(7, debugger.comp.pos);
+}
+
+#[test]
+fn test_debugger_reg_breakpoint() {
+    let program: Vec<_> = include_str!("res/18ex.txt").lines().map(op).collect();
+    let mut debugger = Debugger::new(Comp::new(&program, 0, SoundCard::default()));
+    debugger.add_breakpoint(Breakpoint::parse("a > 0").unwrap());
+    assert_eq!(Stop::Breakpoint(0), debugger.cont().unwrap());
+    assert_eq!(1, debugger.comp.pos);
+    // a stays above 0 until `set a 0`, so the next stop is after `set a 1`
+    assert_eq!(Stop::Breakpoint(0), debugger.cont().unwrap());
+    assert_eq!(9, debugger.comp.pos);
+}
+
+#[test]
+fn test_debugger_trace() {
+    let program: Vec<_> = include_str!("res/18ex2.txt").lines().map(op).collect();
+    let inbox = Queue::default();
+    let device = MessageQueue::new(vec![Queue::default()], inbox.clone());
+    let mut debugger = Debugger::new(Comp::new(&program, 0, device));
+    let path = env::temp_dir().join("aoc-17-day18-trace.txt");
+    debugger.trace_to_file(&path).unwrap();
+    assert_eq!(Stop::Blocked, debugger.run_to_blocked().unwrap());
+    assert_eq!(3, debugger.comp.pos);
+    for val in 0..4 {
+        inbox.borrow_mut().push_front(val);
     }
+    assert_eq!(
+        Ok(String::from("terminated after 7 steps")),
+        debugger.command("run")
+    );
+    drop(debugger);
+    let trace = fs::read_to_string(&path).unwrap();
+    assert_eq!(7, trace.lines().count());
+    assert!(trace.lines().nth(3).unwrap().contains("rcv a"));
+}
+
+#[test]
+fn test_network() {
+    let program: Vec<_> = include_str!("res/18ex2.txt").lines().map(op).collect();
+    // each program sends three values but wants four
+    let report = Network::new(&program, 3, Topology::Ring).run();
+    assert_eq!(Outcome::Deadlock, report.outcome);
+    assert_eq!(vec![3, 3, 3], report.sent);
+    assert_eq!(vec![3, 3, 3], report.received);
+    // with two senders apiece, everyone gets enough to finish
+    let report = Network::new(&program, 3, Topology::Broadcast).run();
+    assert_eq!(Outcome::Terminated, report.outcome);
+    assert_eq!(vec![3, 3, 3], report.sent);
+    assert_eq!(vec![4, 4, 4], report.received);
+    // the odd one out never hears anything
+    let report = Network::new(&program, 3, Topology::Pairs).run();
+    assert_eq!(Outcome::Deadlock, report.outcome);
+    assert_eq!(vec![3, 3, 0], report.received);
+}
+
+#[test]
+fn part2() {
+    let lines = INPUT.lines();
+    let program: Vec<_> = lines.map(op).collect();
+    let report = Network::new(&program, 2, Topology::Pairs).run();
+    println!("Day 18, part 2: {}", report.sent[1]);
+    assert_eq!(Outcome::Deadlock, report.outcome);
+    assert_eq!(7239, report.sent[1]);
 }
 
-#[derive(Debug)]
-struct Comp<'a> {
-    program: &'a [Op],
-    regs: [i64; 16],
-    pos: usize,
-    waiting: bool,
-    snd: Rc<RefCell<VecDeque<i64>>>,
-    rcv: Rc<RefCell<VecDeque<i64>>>,
-    snd_count: u64,
-    id: i64,
-}
-
-impl Comp<'_> {
-    fn new(
-        program: &[Op],
-        id: i64,
-        snd: Rc<RefCell<VecDeque<i64>>>,
-        rcv: Rc<RefCell<VecDeque<i64>>>,
-    ) -> Comp {
-        let mut comp = Comp {
-            program,
-            regs: [0; 16],
-            pos: 0,
-            waiting: false,
-            snd,
-            rcv,
-            snd_count: 0,
-            id,
-        };
-        comp.regs['p' as usize - 'a' as usize] = id;
-        comp
+#[test]
+fn test_profile() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let mut network = Network::new(&program, 2, Topology::Pairs);
+    network.profile();
+    let report = network.run();
+    println!("{}", network.profile_report(10));
+    for (comp, &sent) in network.comps.iter().zip(&report.sent) {
+        let profile = comp.profile.as_ref().unwrap();
+        assert_eq!(profile.steps, profile.per_pc.iter().sum::<u64>());
+        assert_eq!(profile.steps, profile.per_op.values().sum::<u64>());
+        assert_eq!(sent, profile.per_op["snd"]);
+        assert!(profile.high_water > 0);
     }
+    // program 1 skips straight past generating the numbers
+    let profile = network.comps[1].profile.as_ref().unwrap();
+    assert_eq!(0, profile.per_pc[10]);
+    assert_eq!(127, network.comps[0].profile.as_ref().unwrap().per_pc[10]);
+}
 
-    fn get(&self, arg: &Arg) -> i64 {
-        match arg {
-            Arg::Val(v) => *v,
-            Arg::Reg(r) => self.regs[*r],
-        }
+#[test]
+fn test_deadlock_report() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let report = Network::new(&program, 2, Topology::Pairs).run();
+    println!("{}", report);
+    for state in &report.programs {
+        assert_eq!(Some(op("rcv b")), state.blocked_on);
+        assert_eq!(21, state.pos);
+        assert!(state.pending.is_empty());
     }
+    // the odd one out never hears a thing
+    let program: Vec<_> = include_str!("res/18ex2.txt").lines().map(op).collect();
+    let report = Network::new(&program, 3, Topology::Pairs).run();
+    let state = &report.programs[2];
+    assert_eq!(Some(op("rcv a")), state.blocked_on);
+    assert_eq!((3, 3), (state.pos, state.steps));
+    assert!(report
+        .to_string()
+        .contains("program 2: blocked on `rcv a` at pc 3"));
+}
 
-    fn step(&mut self) {
-        if self.waiting && self.rcv.borrow().is_empty() {
-            return;
-        }
-        match self.program[self.pos] {
-            Op::Snd(r) => {
-                self.snd.borrow_mut().push_front(self.regs[r]);
-                self.snd_count += 1;
-            }
-            Op::Rcv(r) => match self.rcv.borrow_mut().pop_back() {
-                Some(val) => {
-                    self.regs[r] = val;
-                    self.waiting = false;
-                }
-                None => {
-                    self.waiting = true;
-                    return;
-                }
-            },
-            Op::Set(r, ref a) => {
-                self.regs[r] = self.get(a);
-            }
-            Op::Add(r, ref a) => {
-                self.regs[r] += self.get(a);
-            }
-            Op::Mul(r, ref a) => {
-                self.regs[r] *= self.get(a);
-            }
-            Op::Mod(r, ref a) => {
-                self.regs[r] %= self.get(a);
-            }
-            Op::Jgz(r, ref a) => {
-                if self.regs[r] > 0 {
-                    self.pos = (self.pos as i64 + self.get(a)) as usize;
-                    return;
-                }
-            }
+#[test]
+fn test_threaded() {
+    let program: Vec<_> = include_str!("res/18ex2.txt").lines().map(op).collect();
+    for topology in [Topology::Ring, Topology::Pairs] {
+        for n in 1..=4 {
+            let expected = Network::new(&program, n, topology).run();
+            assert_eq!(expected, run_threaded(&program, n, topology));
         }
-        self.pos += 1;
+    }
+    // with several senders per inbox, who gets what depends on the threads,
+    // but how much doesn't
+    for n in 1..=4 {
+        let expected = Network::new(&program, n, Topology::Broadcast).run();
+        let report = run_threaded(&program, n, Topology::Broadcast);
+        assert_eq!(expected.outcome, report.outcome);
+        assert_eq!(expected.sent, report.sent);
+        assert_eq!(expected.received, report.received);
     }
 }
 
 #[test]
-fn part1() {
-    let lines = INPUT.lines();
-    let program: Vec<_> = lines.map(Op::parse).collect();
-    let snd = Rc::new(RefCell::new(VecDeque::new()));
-    let rcv = Rc::new(RefCell::new(VecDeque::new()));
-    let mut comp = Comp::new(&program, 0, snd, rcv);
+fn part2_threaded() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let expected = Network::new(&program, 2, Topology::Pairs).run();
+    // run it a few times to shake out any races
+    for _ in 0..20 {
+        let report = run_threaded(&program, 2, Topology::Pairs);
+        assert_eq!(expected, report);
+        assert_eq!(7239, report.sent[1]);
+    }
+}
+
+/// counts down a pair of nested loops, adding up the inner counter in `c`
+fn nested_loops(outer: i64, inner: i64) -> Vec<Op> {
+    let source = format!(
+        "set a {}\nset b {}\nadd c b\nadd b -1\njgz b -2\nadd a -1\njgz a -5\nsnd c",
+        outer, inner
+    );
+    source.lines().map(op).collect()
+}
 
-    while comp.waiting == false {
+fn run_slow(program: &[Op]) -> Comp<'_, MessageQueue> {
+    let device = MessageQueue::new(vec![Queue::default()], Queue::default());
+    let mut comp = Comp::new(program, 0, device);
+    while !comp.terminated() {
         comp.step();
     }
+    comp
+}
 
-    let i = comp.snd.borrow();
-    let ans = i.iter().next().unwrap();
+#[test]
+fn test_fast() {
+    let program = nested_loops(50, 50);
+    let comp = run_slow(&program);
+    let mut machine = fast::Machine::new(0);
+    let mut outbox = VecDeque::new();
+    let state = machine.run(&fast::decode(&program), &mut VecDeque::new(), &mut outbox);
+    assert_eq!(fast::State::Terminated, state);
+    assert_eq!(comp.regs, machine.regs);
+    assert_eq!(50 * (50 * 51 / 2), machine.regs[2]);
+    assert_eq!(comp.device.snd[0].borrow().front(), outbox.front());
+}
 
-    println!("Day 18, part 1: {}", ans);
-    assert_eq!(8600, *ans);
+#[test]
+fn part2_fast() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let [m0, m1] = fast::run_pair(&fast::decode(&program));
+    let report = Network::new(&program, 2, Topology::Pairs).run();
+    assert_eq!(report.sent, vec![m0.snd_count, m1.snd_count]);
+    assert_eq!(report.received, vec![m0.rcv_count, m1.rcv_count]);
+    assert_eq!(7239, m1.snd_count);
 }
 
+/// `cargo test --release --test day18 bench_fast -- --ignored --nocapture`
 #[test]
-fn part2() {
-    let lines = INPUT.lines();
-    let program: Vec<_> = lines.map(Op::parse).collect();
-    let q1 = Rc::new(RefCell::new(VecDeque::new()));
-    let q2 = Rc::new(RefCell::new(VecDeque::new()));
-    let mut comp1 = Comp::new(&program, 0, q1.clone(), q2.clone());
-    let mut comp2 = Comp::new(&program, 1, q2.clone(), q1.clone());
-    while !(comp1.waiting && comp2.waiting) {
-        comp1.step();
-        comp2.step();
+#[ignore]
+fn bench_fast() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let start = Instant::now();
+    for _ in 0..100 {
+        Network::new(&program, 2, Topology::Pairs).run();
+    }
+    let slow = start.elapsed();
+    let start = Instant::now();
+    for _ in 0..100 {
+        fast::run_pair(&fast::decode(&program));
+    }
+    let quick = start.elapsed();
+    println!("day 18 input x100: {:?} -> {:?}", slow, quick);
+
+    let program = nested_loops(2000, 2000);
+    let start = Instant::now();
+    run_slow(&program);
+    let slow = start.elapsed();
+    let start = Instant::now();
+    let mut machine = fast::Machine::new(0);
+    let code = fast::decode(&program);
+    machine.run(&code, &mut VecDeque::new(), &mut VecDeque::new());
+    let quick = start.elapsed();
+    println!("nested loops, 2000x2000: {:?} -> {:?}", slow, quick);
+}
+
+#[test]
+fn test_round_trip() {
+    let sources = [
+        include_str!("res/18.txt"),
+        include_str!("res/18ex.txt"),
+        include_str!("res/18ex2.txt"),
+    ];
+    for source in sources {
+        let program = asm::assemble(source).unwrap();
+        let parsed: Vec<_> = source.lines().map(op).collect();
+        assert_eq!(parsed, program);
+        assert_eq!(source, asm::disassemble(&program, false));
+        let labelled = asm::disassemble(&program, true);
+        assert_eq!(program, asm::assemble(&labelled).unwrap());
     }
-    println!("Day 18, part 2: {}", comp2.snd_count);
-    assert_eq!(7239, comp2.snd_count);
+}
+
+#[test]
+fn test_assemble() {
+    let source = "
+        set a 1     # comment
+        twice: add a 2
+        mul a a ; another comment
+        mod a 5
+        snd a
+        set a 0
+        recover: rcv a
+        jgz a recover
+        set a 1
+        jgz a twice
+        jgz a end
+        end:
+    ";
+    let program = asm::assemble(source).unwrap();
+    let example: Vec<_> = include_str!("res/18ex.txt").lines().map(op).collect();
+    assert_eq!(example[..8], program[..8]);
+    assert_eq!(op("jgz a -8"), program[9]);
+    assert_eq!(op("jgz a 1"), program[10]);
+    let err = asm::assemble("set a 1\njgz a nowhere").unwrap_err();
+    assert_eq!("line 2: unknown label: nowhere", err.to_string());
+    assert!(asm::assemble("a: set a 1").is_err());
+    assert!(asm::assemble("set q 1").is_err());
+}
+
+#[test]
+fn test_cfg() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let graph = cfg::Cfg::build(&program);
+    assert_eq!(13, graph.blocks.len());
+    assert_eq!(
+        vec![cfg::Edge::Next(1), cfg::Edge::Indirect],
+        graph.blocks[0].succs
+    );
+    let headers: Vec<_> = graph
+        .loops()
+        .iter()
+        .map(|l| graph.blocks[l.header].start)
+        .collect();
+    assert_eq!(vec![4, 10, 21, 23, 26], headers);
+    let dot = graph.to_dot();
+    assert!(dot.contains("b12 -> exit"));
+    assert!(dot.contains("b11 -> b6 [label=\"f > 0\"]"));
+    let empty = cfg::Cfg::build(&[]);
+    assert!(empty.blocks.is_empty());
+    assert!(empty.loops().is_empty());
+}
+
+#[test]
+fn test_cfg_unreachable() {
+    let build = |source: &str| -> Vec<Op> { source.lines().map(op).collect() };
+    // the `add` is dead code, so its edge on to the `snd` isn't a back edge
+    let program = build("jgz 1 2\nadd a 1\nsnd a");
+    let graph = cfg::Cfg::build(&program);
+    assert_eq!(3, graph.blocks.len());
+    assert_eq!(Vec::<cfg::Loop>::new(), graph.loops());
+
+    // only the register jump could get to the `add`
+    let program = build("jgz 1 p\nadd a 1\njgz a 2\nsnd a\nsnd a");
+    let graph = cfg::Cfg::build(&program);
+    assert_eq!(vec![cfg::Edge::Indirect], graph.blocks[0].succs);
+    assert_eq!(Vec::<cfg::Loop>::new(), graph.loops());
+
+    // a real loop after some dead code is still found
+    let program = build("jgz 1 2\nsnd a\nadd a -1\njgz a -1");
+    let graph = cfg::Cfg::build(&program);
+    let headers: Vec<_> = graph
+        .loops()
+        .iter()
+        .map(|l| graph.blocks[l.header].start)
+        .collect();
+    assert_eq!(vec![2], headers);
+}
+
+#[test]
+fn test_decompile() {
+    let program: Vec<_> = INPUT.lines().map(op).collect();
+    let code = cfg::decompile(&program);
+    println!("{}", code);
+    let expected = "
+    do {
+        b = receive()
+        p = a
+        p *= -1
+        p += b
+        if !(p > 0) {
+            send(a)
+            a = b
+        } else {
+            send(b)
+            f = 1
+        }
+        i -= 1
+    } while i > 0
+    send(a)
+} while f > 0
+if a > 0 { goto L21 }";
+    assert!(code.ends_with(expected));
+    assert!(code.contains("L21:\n    do {\n        b = receive()\n    } while b > 0"));
 }